@@ -0,0 +1,638 @@
+//! Admin endpoints to inspect and invalidate the caches, when the data they were computed from
+//! got corrected (recomputed scores, a resolved shutter reveal, ...).
+//!
+//! They require `Authorization: Bearer <ADMIN_TOKEN>`, and are disabled if `ADMIN_TOKEN` is not
+//! set. Evicting an entry also removes it from the persistent store.
+
+use crate::lottery::CACHED_LOTTERY_WINNERS;
+use crate::routes::{
+    BoostKey, CACHED_ALLOCATION, CACHED_MERKLE_DISTRIBUTION, GET_PROPOSAL_INFO, GET_VOTE_INFO,
+};
+use crate::store::{Artifact, Store};
+use crate::{parse_request, ServerError, State};
+use axum::extract::Json;
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use axum::Extension;
+use cached::{Cached, TimedSizedCache};
+use ethers::types::{Address, U256};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::hash::Hash;
+use std::str::FromStr;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub name: String,
+    pub size: usize,
+    pub capacity: Option<usize>,
+    pub hits: u64,
+    pub misses: u64,
+    // Share of the lookups answered by the cache, from 0 to 1
+    pub hit_rate: f64,
+    // Artifacts kept in the persistent store
+    pub stored: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheFilter {
+    // Name of the cache, as listed by `/admin/caches`: every cache if not set
+    pub cache: Option<String>,
+    pub proposal_id: Option<String>,
+    pub voter_address: Option<String>,
+    pub boost_id: Option<String>,
+    pub chain_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub cache: String,
+    pub key: Value,
+    pub value: Value,
+    // Seconds since the entry was cached
+    pub age: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Evicted {
+    pub cache: String,
+    // Entries removed from memory
+    pub cached: usize,
+    // Artifacts removed from the persistent store
+    pub stored: usize,
+}
+
+// What a cache key refers to, to match it against a filter
+#[derive(Debug, Default)]
+struct KeyParts {
+    proposal_id: Option<String>,
+    voter: Option<Address>,
+    boost: Option<(u64, U256)>,
+}
+
+// Parsed filter: an entry matches if every given criterion matches
+#[derive(Debug)]
+struct Matcher {
+    cache: Option<String>,
+    proposal_id: Option<String>,
+    voter: Option<Address>,
+    boost: Option<(u64, U256)>,
+}
+
+impl Matcher {
+    fn new(filter: CacheFilter) -> Result<Self, ServerError> {
+        let invalid = |field: &str| ServerError::InvalidInput(format!("invalid {}", field));
+
+        if let Some(cache) = &filter.cache {
+            if !CACHES.contains(&cache.as_str()) {
+                return Err(ServerError::InvalidInput(format!(
+                    "unknown cache {}",
+                    cache
+                )));
+            }
+        }
+        let voter = filter
+            .voter_address
+            .map(|voter| Address::from_str(&voter).map_err(|_| invalid("voter_address")))
+            .transpose()?;
+        let boost = match (filter.boost_id, filter.chain_id) {
+            (Some(boost_id), Some(chain_id)) => Some((
+                boost_id.parse().map_err(|_| invalid("boost_id"))?,
+                U256::from_dec_str(&chain_id).map_err(|_| invalid("chain_id"))?,
+            )),
+            (None, None) => None,
+            _ => {
+                return Err(ServerError::InvalidInput(
+                    "boost_id and chain_id go together".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            cache: filter.cache,
+            proposal_id: filter.proposal_id,
+            voter,
+            boost,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.proposal_id.is_none() && self.voter.is_none() && self.boost.is_none()
+    }
+
+    fn matches_cache(&self, cache: &str) -> bool {
+        self.cache.as_deref().is_none_or(|c| c == cache)
+    }
+
+    fn matches(&self, key: &KeyParts) -> bool {
+        fn criterion<T: PartialEq>(wanted: &Option<T>, actual: &Option<T>) -> bool {
+            wanted.is_none() || (actual.is_some() && wanted == actual)
+        }
+        criterion(&self.proposal_id, &key.proposal_id)
+            && criterion(&self.voter, &key.voter)
+            && criterion(&self.boost, &key.boost)
+    }
+}
+
+trait CacheKey: Clone + Eq + Hash + Serialize + DeserializeOwned {
+    fn parts(&self) -> KeyParts;
+}
+
+impl CacheKey for String {
+    fn parts(&self) -> KeyParts {
+        KeyParts {
+            proposal_id: Some(self.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+impl CacheKey for (String, String) {
+    fn parts(&self) -> KeyParts {
+        KeyParts {
+            proposal_id: Some(self.1.clone()),
+            voter: Address::from_str(&self.0).ok(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CacheKey for BoostKey {
+    fn parts(&self) -> KeyParts {
+        KeyParts {
+            proposal_id: Some(self.proposal.clone()),
+            boost: Some((self.boost_id, self.chain_id)),
+            ..Default::default()
+        }
+    }
+}
+
+const CACHES: [&str; 5] = [
+    "proposal_info",
+    "vote_info",
+    "allocation",
+    "lottery_winners",
+    "merkle_distribution",
+];
+
+// Artifact kept in the persistent store along with a cache
+fn artifact(cache: &str) -> Option<Artifact> {
+    match cache {
+        "proposal_info" => Some(Artifact::Proposal),
+        "vote_info" => Some(Artifact::Vote),
+        "allocation" => Some(Artifact::Allocation),
+        "lottery_winners" => Some(Artifact::LotteryWinners),
+        _ => None,
+    }
+}
+
+// Artifacts removed from the persistent store when a cache is evicted: the seed of a lottery goes
+// along with its winners, so that a redraw does not leave the seed of the previous one behind
+fn evicted_artifacts(cache: &str) -> &'static [Artifact] {
+    match cache {
+        "proposal_info" => &[Artifact::Proposal],
+        "vote_info" => &[Artifact::Vote],
+        "allocation" => &[Artifact::Allocation],
+        "lottery_winners" => &[Artifact::LotteryWinners, Artifact::LotterySeed],
+        _ => &[],
+    }
+}
+
+pub(crate) fn authorize(state: &State, headers: &HeaderMap) -> Result<(), ServerError> {
+    let expected = state
+        .admin_token
+        .as_deref()
+        .ok_or_else(|| ServerError::NotFound("admin endpoints are disabled".to_string()))?;
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ServerError::Unauthorized("missing admin token".to_string()))?;
+
+    // Constant time, so that the token can not be guessed byte after byte
+    let equal = token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+    if equal {
+        Ok(())
+    } else {
+        Err(ServerError::Unauthorized("wrong admin token".to_string()))
+    }
+}
+
+pub async fn handle_cache_stats(
+    Extension(state): Extension<State>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    authorize(&state, &headers)?;
+    Ok(Json(cache_stats(&state.store).await?))
+}
+
+pub async fn handle_inspect_cache(
+    Extension(state): Extension<State>,
+    headers: HeaderMap,
+    Json(p): Json<Value>,
+) -> Result<impl IntoResponse, ServerError> {
+    authorize(&state, &headers)?;
+    let filter: CacheFilter = parse_request(p)?;
+    Ok(Json(inspect_caches(Matcher::new(filter)?).await?))
+}
+
+pub async fn handle_evict_cache(
+    Extension(state): Extension<State>,
+    headers: HeaderMap,
+    Json(p): Json<Value>,
+) -> Result<impl IntoResponse, ServerError> {
+    authorize(&state, &headers)?;
+    let filter: CacheFilter = parse_request(p)?;
+    let matcher = Matcher::new(filter)?;
+    // Evicting everything at once is never what was meant
+    if matcher.is_empty() {
+        return Err(ServerError::InvalidInput(
+            "evict by proposal_id, voter_address or boost_id and chain_id".to_string(),
+        ));
+    }
+    let evicted = evict_caches(&state.store, &matcher).await?;
+    tracing::info!(?matcher, ?evicted, "evicted caches");
+    Ok(Json(evicted))
+}
+
+pub async fn cache_stats(store: &Store) -> Result<Vec<CacheStats>, ServerError> {
+    Ok(vec![
+        stats("proposal_info", &*GET_PROPOSAL_INFO.lock().await, store)?,
+        stats("vote_info", &*GET_VOTE_INFO.lock().await, store)?,
+        stats("allocation", &*CACHED_ALLOCATION.lock().await, store)?,
+        stats(
+            "lottery_winners",
+            &*CACHED_LOTTERY_WINNERS.lock().await,
+            store,
+        )?,
+        stats(
+            "merkle_distribution",
+            &*CACHED_MERKLE_DISTRIBUTION.lock().await,
+            store,
+        )?,
+    ])
+}
+
+async fn inspect_caches(matcher: Matcher) -> Result<Vec<CacheEntry>, ServerError> {
+    let mut entries = Vec::new();
+    entries.extend(inspect(
+        "proposal_info",
+        &*GET_PROPOSAL_INFO.lock().await,
+        &matcher,
+        |proposal| serde_json::to_value(proposal),
+    )?);
+    entries.extend(inspect(
+        "vote_info",
+        &*GET_VOTE_INFO.lock().await,
+        &matcher,
+        |vote| serde_json::to_value(vote),
+    )?);
+    entries.extend(inspect(
+        "allocation",
+        &*CACHED_ALLOCATION.lock().await,
+        &matcher,
+        |allocation| serde_json::to_value(&**allocation),
+    )?);
+    entries.extend(inspect(
+        "lottery_winners",
+        &*CACHED_LOTTERY_WINNERS.lock().await,
+        &matcher,
+        |winners| serde_json::to_value(&**winners),
+    )?);
+    // The tree itself is too large to be useful
+    entries.extend(inspect(
+        "merkle_distribution",
+        &*CACHED_MERKLE_DISTRIBUTION.lock().await,
+        &matcher,
+        |distribution| {
+            Ok(serde_json::json!({
+                "root": distribution.tree.root(),
+                "recipients": distribution.rewards.len(),
+                "total": distribution.total.to_string(),
+            }))
+        },
+    )?);
+    Ok(entries)
+}
+
+async fn evict_caches(store: &Store, matcher: &Matcher) -> Result<Vec<Evicted>, ServerError> {
+    Ok(vec![
+        evict(
+            "proposal_info",
+            &mut *GET_PROPOSAL_INFO.lock().await,
+            store,
+            matcher,
+        )?,
+        evict(
+            "vote_info",
+            &mut *GET_VOTE_INFO.lock().await,
+            store,
+            matcher,
+        )?,
+        evict(
+            "allocation",
+            &mut *CACHED_ALLOCATION.lock().await,
+            store,
+            matcher,
+        )?,
+        evict(
+            "lottery_winners",
+            &mut *CACHED_LOTTERY_WINNERS.lock().await,
+            store,
+            matcher,
+        )?,
+        evict(
+            "merkle_distribution",
+            &mut *CACHED_MERKLE_DISTRIBUTION.lock().await,
+            store,
+            matcher,
+        )?,
+    ]
+    .into_iter()
+    .filter(|evicted| evicted.cached > 0 || evicted.stored > 0)
+    .collect())
+}
+
+fn stats<K: CacheKey, V>(
+    name: &str,
+    cache: &TimedSizedCache<K, V>,
+    store: &Store,
+) -> Result<CacheStats, ServerError> {
+    let hits = cache.cache_hits().unwrap_or_default();
+    let misses = cache.cache_misses().unwrap_or_default();
+    let lookups = hits + misses;
+    Ok(CacheStats {
+        name: name.to_string(),
+        size: cache.cache_size(),
+        capacity: cache.cache_capacity(),
+        hits,
+        misses,
+        hit_rate: if lookups == 0 {
+            0.0
+        } else {
+            hits as f64 / lookups as f64
+        },
+        stored: match artifact(name) {
+            Some(artifact) => store.count(artifact)?,
+            None => 0,
+        },
+    })
+}
+
+fn inspect<K: CacheKey, V>(
+    name: &str,
+    cache: &TimedSizedCache<K, V>,
+    matcher: &Matcher,
+    value: impl Fn(&V) -> serde_json::Result<Value>,
+) -> Result<Vec<CacheEntry>, ServerError> {
+    if !matcher.matches_cache(name) {
+        return Ok(Vec::new());
+    }
+    let internal = |e: serde_json::Error| ServerError::Internal(e.to_string());
+
+    cache
+        .key_order()
+        .zip(cache.value_order())
+        .filter(|(key, _)| matcher.matches(&key.parts()))
+        .map(|(key, (cached_at, v))| {
+            Ok(CacheEntry {
+                cache: name.to_string(),
+                key: serde_json::to_value(key).map_err(internal)?,
+                value: value(v).map_err(internal)?,
+                age: cached_at.elapsed().as_secs(),
+            })
+        })
+        .collect()
+}
+
+fn evict<K: CacheKey, V>(
+    name: &str,
+    cache: &mut TimedSizedCache<K, V>,
+    store: &Store,
+    matcher: &Matcher,
+) -> Result<Evicted, ServerError> {
+    let mut evicted = Evicted {
+        cache: name.to_string(),
+        cached: 0,
+        stored: 0,
+    };
+    if !matcher.matches_cache(name) {
+        return Ok(evicted);
+    }
+
+    let keys: Vec<K> = cache
+        .key_order()
+        .filter(|key| matcher.matches(&key.parts()))
+        .cloned()
+        .collect();
+    for key in keys {
+        if cache.cache_remove(&key).is_some() {
+            evicted.cached += 1;
+        }
+    }
+
+    for &artifact in evicted_artifacts(name) {
+        for key in store.keys::<K>(artifact)? {
+            if matcher.matches(&key.parts()) && store.remove(artifact, &key)? {
+                evicted.stored += 1;
+            }
+        }
+    }
+
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon::InMemoryBeacon;
+    use crate::hub::InMemoryHub;
+    use crate::lottery::cached_lottery_winners;
+    use crate::routes::{BoostInfo, BoostParams, DistributionType, ProposalInfo, VoteWithChoice};
+    use ethers::types::H256;
+
+    fn matcher(filter: CacheFilter) -> Matcher {
+        Matcher::new(filter).unwrap()
+    }
+
+    #[test]
+    fn test_matcher() {
+        let voter = Address::random();
+        let vote = (format!("{:?}", voter), "0x11".to_string());
+        let boost = BoostInfo {
+            id: 1,
+            chain_id: U256::from(11155111),
+            params: BoostParams {
+                proposal: "0x11".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .cache_key();
+
+        let by_proposal = matcher(CacheFilter {
+            proposal_id: Some("0x11".to_string()),
+            ..Default::default()
+        });
+        assert!(by_proposal.matches(&"0x11".to_string().parts()));
+        assert!(!by_proposal.matches(&"0x12".to_string().parts()));
+        assert!(by_proposal.matches(&vote.parts()));
+        assert!(by_proposal.matches(&boost.parts()));
+
+        // Addresses match whatever their case
+        let by_voter = matcher(CacheFilter {
+            voter_address: Some(to_checksum(&voter)),
+            ..Default::default()
+        });
+        assert!(by_voter.matches(&vote.parts()));
+        assert!(!by_voter.matches(&"0x11".to_string().parts()));
+        assert!(!by_voter.matches(&boost.parts()));
+
+        let by_boost = |boost_id: &str, chain_id: &str| {
+            matcher(CacheFilter {
+                boost_id: Some(boost_id.to_string()),
+                chain_id: Some(chain_id.to_string()),
+                ..Default::default()
+            })
+        };
+        assert!(by_boost("1", "11155111").matches(&boost.parts()));
+        assert!(!by_boost("11", "1155111").matches(&boost.parts()));
+        assert!(!by_boost("1", "11155111").matches(&vote.parts()));
+
+        for invalid in [
+            CacheFilter {
+                cache: Some("unknown".to_string()),
+                ..Default::default()
+            },
+            CacheFilter {
+                voter_address: Some("0x12".to_string()),
+                ..Default::default()
+            },
+            CacheFilter {
+                boost_id: Some("1".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(Matcher::new(invalid).unwrap_err().code(), "invalid_input");
+        }
+    }
+
+    fn to_checksum(address: &Address) -> String {
+        ethers::utils::to_checksum(address, None)
+    }
+
+    #[tokio::test]
+    async fn test_evict_vote() {
+        let store = Store::in_memory().unwrap();
+        // A voter of its own, so that no other test uses the entry
+        let voter = Address::random();
+        let proposal_id = "0x11".to_string();
+        let vote = VoteWithChoice {
+            voter,
+            ..Default::default()
+        };
+        let cached_key = (to_checksum(&voter), proposal_id.clone());
+        let stored_key = (format!("{:?}", voter), proposal_id.clone());
+        GET_VOTE_INFO
+            .lock()
+            .await
+            .cache_set(cached_key.clone(), vote.clone());
+        store.put(Artifact::Vote, &stored_key, &vote).unwrap();
+
+        let by_voter = || {
+            matcher(CacheFilter {
+                cache: Some("vote_info".to_string()),
+                voter_address: Some(format!("{:?}", voter)),
+                ..Default::default()
+            })
+        };
+        let entries = inspect_caches(by_voter()).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].cache, "vote_info");
+        assert_eq!(entries[0].value["choice"], "1");
+
+        let evicted = evict_caches(&store, &by_voter()).await.unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].cache, "vote_info");
+        assert_eq!(evicted[0].cached, 1);
+        assert_eq!(evicted[0].stored, 1);
+
+        assert!(inspect_caches(by_voter()).await.unwrap().is_empty());
+        assert!(GET_VOTE_INFO.lock().await.cache_get(&cached_key).is_none());
+        assert_eq!(
+            store
+                .get::<_, VoteWithChoice>(Artifact::Vote, &stored_key)
+                .unwrap()
+                .map(|v| v.voter),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evict_lottery_then_redraw() {
+        let store = Store::in_memory().unwrap();
+        let proposal_info = ProposalInfo {
+            id: "evict_lottery".to_string(),
+            scores_state: "final".to_string(),
+            ..Default::default()
+        };
+        let lottery = BoostInfo {
+            id: 401,
+            chain_id: U256::from(11155111),
+            params: BoostParams {
+                proposal: proposal_info.id.clone(),
+                distribution: DistributionType::Lottery(3, None),
+                ..Default::default()
+            },
+            pool_size: U256::from(100),
+            ..Default::default()
+        };
+        let hub_with_voters = |voters: usize| {
+            let hub = InMemoryHub::default();
+            for _ in 0..voters {
+                hub.insert_vote(&proposal_info.id, VoteWithChoice::default());
+            }
+            hub
+        };
+        let beacon = InMemoryBeacon::new("0x00");
+        let seed = || {
+            store
+                .get::<_, H256>(Artifact::LotterySeed, &lottery.cache_key())
+                .unwrap()
+        };
+
+        let hub = hub_with_voters(10);
+        cached_lottery_winners(&hub, &beacon, &store, &lottery, &proposal_info, 3, None)
+            .await
+            .unwrap();
+        assert!(seed().is_some());
+
+        let by_boost = matcher(CacheFilter {
+            cache: Some("lottery_winners".to_string()),
+            boost_id: Some("401".to_string()),
+            chain_id: Some("11155111".to_string()),
+            ..Default::default()
+        });
+        let evicted = evict_caches(&store, &by_boost).await.unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].cached, 1);
+        // The winners and the seed they were drawn with
+        assert_eq!(evicted[0].stored, 2);
+        assert!(seed().is_none());
+
+        // Too few voters for a draw this time: no seed of the previous draw is left behind
+        let hub = hub_with_voters(2);
+        let winners =
+            cached_lottery_winners(&hub, &beacon, &store, &lottery, &proposal_info, 3, None)
+                .await
+                .unwrap();
+        assert_eq!(winners.len(), 2);
+        assert!(seed().is_none());
+    }
+}
@@ -0,0 +1,548 @@
+//! Append-only log of every voucher signed by the guard, for security reviews and incident
+//! response.
+//!
+//! Each voucher is recorded with the request it was signed for and the inputs its reward was
+//! computed from. The log is kept in SQLite at `AUDIT_LOG_PATH`, apart from the store of finalized
+//! results: evicting caches never touches it, and triggers reject any update or deletion.
+//!
+//! The log is also the ledger of the claims: once a voucher is issued for a boost and a recipient,
+//! the guard hands out that same voucher again rather than signing another amount.
+//!
+//! The signed root of a Merkle distribution is recorded as a voucher to the zero address, for the
+//! total of the tree: a boost gets a single root.
+
+use crate::admin::authorize;
+use crate::{parse_request, ServerError, State};
+use axum::extract::Json;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::Extension;
+use ethers::types::{Address, H256};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// Number of entries returned by `/admin/vouchers` if the request sets no limit, and at most
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoucherRecord {
+    // Unix timestamp of the signature
+    pub signed_at: u64,
+    // Id of the request, as traced by `create_vouchers`
+    pub request_id: String,
+    pub proposal_id: String,
+    pub boost_id: String,
+    pub chain_id: String,
+    // The boost contract the voucher is claimed from
+    pub verifying_contract: Address,
+    // The zero address for the root of a Merkle distribution
+    pub recipient: Address,
+    pub amount: String,
+    // EIP712 digest of the claim or of the root, as signed
+    pub digest: H256,
+    pub signature: String,
+    // Inputs the reward was computed from
+    pub scores_state: String,
+    pub voting_power: String,
+    // Seed the lottery was drawn with, for lottery boosts that needed a draw
+    pub seed: Option<H256>,
+}
+
+impl VoucherRecord {
+    pub fn claim_key(&self) -> ClaimKey {
+        ClaimKey {
+            chain_id: self.chain_id.clone(),
+            verifying_contract: self.verifying_contract,
+            boost_id: self.boost_id.clone(),
+            recipient: self.recipient,
+        }
+    }
+}
+
+/// What a voucher pays out: at most one voucher is issued per claim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimKey {
+    pub chain_id: String,
+    pub verifying_contract: Address,
+    pub boost_id: String,
+    pub recipient: Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // Position in the log
+    pub id: u64,
+    #[serde(flatten)]
+    pub voucher: VoucherRecord,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub proposal_id: Option<String>,
+    pub boost_id: Option<String>,
+    pub chain_id: Option<String>,
+    pub recipient: Option<String>,
+    pub request_id: Option<String>,
+    // Only the entries after this id, to page through the log
+    pub after: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    conn: Mutex<Connection>,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if needed.
+    pub fn open(path: &str) -> Result<Self, ServerError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A log that is lost when the process exits.
+    pub fn in_memory() -> Result<Self, ServerError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Opens the log at `AUDIT_LOG_PATH`, or an in-memory one if it is not set.
+    pub fn from_env() -> Result<Self, ServerError> {
+        match std::env::var("AUDIT_LOG_PATH") {
+            Ok(path) => Self::open(&path),
+            Err(_) => {
+                tracing::warn!("AUDIT_LOG_PATH is not set: signed vouchers will not persist");
+                Self::in_memory()
+            }
+        }
+    }
+
+    fn init(conn: Connection) -> Result<Self, ServerError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS vouchers (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                signed_at INTEGER NOT NULL,
+                request_id TEXT NOT NULL,
+                proposal_id TEXT NOT NULL,
+                boost_id TEXT NOT NULL,
+                chain_id TEXT NOT NULL,
+                verifying_contract TEXT NOT NULL,
+                recipient TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                digest TEXT NOT NULL,
+                signature TEXT NOT NULL,
+                scores_state TEXT NOT NULL,
+                voting_power TEXT NOT NULL,
+                seed TEXT
+            );
+            CREATE TRIGGER IF NOT EXISTS vouchers_no_update BEFORE UPDATE ON vouchers
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+            CREATE TRIGGER IF NOT EXISTS vouchers_no_delete BEFORE DELETE ON vouchers
+            BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+
+            CREATE TABLE IF NOT EXISTS issued (
+                chain_id TEXT NOT NULL,
+                verifying_contract TEXT NOT NULL,
+                boost_id TEXT NOT NULL,
+                recipient TEXT NOT NULL,
+                voucher_id INTEGER NOT NULL REFERENCES vouchers (id),
+                PRIMARY KEY (chain_id, verifying_contract, boost_id, recipient)
+            );
+            CREATE TRIGGER IF NOT EXISTS issued_no_update BEFORE UPDATE ON issued
+            BEGIN SELECT RAISE(ABORT, 'the ledger is append-only'); END;
+            CREATE TRIGGER IF NOT EXISTS issued_no_delete BEFORE DELETE ON issued
+            BEGIN SELECT RAISE(ABORT, 'the ledger is append-only'); END;
+
+            -- Logs written before the ledger: the first voucher of each claim is the one issued
+            INSERT OR IGNORE INTO issued
+            SELECT chain_id, verifying_contract, boost_id, recipient, MIN(id)
+            FROM vouchers
+            GROUP BY chain_id, verifying_contract, boost_id, recipient;",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The voucher issued for this claim, if any.
+    pub fn issued(&self, claim: &ClaimKey) -> Result<Option<AuditEntry>, ServerError> {
+        issued(&self.conn.lock().unwrap(), claim)
+    }
+
+    /// Issues the voucher of a claim: `sign` is only called if no voucher was issued for it yet,
+    /// and the voucher it signs is recorded before anyone else can claim it. Otherwise the first
+    /// voucher is returned, and nothing gets signed.
+    pub fn issue(
+        &self,
+        claim: &ClaimKey,
+        sign: impl FnOnce() -> Result<VoucherRecord, ServerError>,
+    ) -> Result<AuditEntry, ServerError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if let Some(first) = issued(&tx, claim)? {
+            return Ok(first);
+        }
+
+        let voucher = sign()?;
+        if voucher.claim_key() != *claim {
+            return Err(ServerError::Internal(format!(
+                "voucher signed for another claim than {:?}",
+                claim
+            )));
+        }
+
+        tx.execute(
+            "INSERT INTO vouchers (signed_at, request_id, proposal_id, boost_id, chain_id,
+                verifying_contract, recipient, amount, digest, signature, scores_state,
+                voting_power, seed)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                voucher.signed_at as i64,
+                voucher.request_id,
+                voucher.proposal_id,
+                voucher.boost_id,
+                voucher.chain_id,
+                format!("{:?}", voucher.verifying_contract),
+                format!("{:?}", voucher.recipient),
+                voucher.amount,
+                format!("{:?}", voucher.digest),
+                voucher.signature,
+                voucher.scores_state,
+                voucher.voting_power,
+                voucher.seed.map(|seed| format!("{:?}", seed)),
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO issued (chain_id, verifying_contract, boost_id, recipient, voucher_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                claim.chain_id,
+                format!("{:?}", claim.verifying_contract),
+                claim.boost_id,
+                format!("{:?}", claim.recipient),
+                id,
+            ],
+        )?;
+        tx.commit()?;
+
+        Ok(AuditEntry {
+            id: id as u64,
+            voucher,
+        })
+    }
+
+    /// Entries matching every criterion of the filter, oldest first.
+    pub fn entries(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, ServerError> {
+        // Addresses are recorded in lowercase
+        let recipient = filter
+            .recipient
+            .as_deref()
+            .map(|recipient| {
+                Address::from_str(recipient)
+                    .map(|recipient| format!("{:?}", recipient))
+                    .map_err(|_| ServerError::InvalidInput("invalid recipient".to_string()))
+            })
+            .transpose()?;
+
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {}
+            FROM vouchers
+            WHERE (?1 IS NULL OR proposal_id = ?1)
+            AND (?2 IS NULL OR boost_id = ?2)
+            AND (?3 IS NULL OR chain_id = ?3)
+            AND (?4 IS NULL OR recipient = ?4)
+            AND (?5 IS NULL OR request_id = ?5)
+            AND id > ?6
+            ORDER BY id
+            LIMIT ?7",
+            COLUMNS
+        ))?;
+        let entries = statement
+            .query_map(
+                params![
+                    filter.proposal_id,
+                    filter.boost_id,
+                    filter.chain_id,
+                    recipient,
+                    filter.request_id,
+                    filter.after.unwrap_or(0) as i64,
+                    // A negative limit means no limit
+                    filter.limit.map_or(-1, i64::from),
+                ],
+                entry_from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+}
+
+// Columns of the log, in the order read by `entry_from_row`
+const COLUMNS: &str = "id, signed_at, request_id, proposal_id, boost_id, chain_id, \
+    verifying_contract, recipient, amount, digest, signature, scores_state, voting_power, seed";
+
+fn issued(conn: &Connection, claim: &ClaimKey) -> Result<Option<AuditEntry>, ServerError> {
+    let entry = conn
+        .query_row(
+            &format!(
+                "SELECT {}
+                FROM vouchers
+                WHERE id = (
+                    SELECT voucher_id FROM issued
+                    WHERE chain_id = ?1 AND verifying_contract = ?2 AND boost_id = ?3
+                    AND recipient = ?4
+                )",
+                COLUMNS
+            ),
+            params![
+                claim.chain_id,
+                format!("{:?}", claim.verifying_contract),
+                claim.boost_id,
+                format!("{:?}", claim.recipient),
+            ],
+            entry_from_row,
+        )
+        .optional()?;
+    Ok(entry)
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<AuditEntry> {
+    // Columns are only ever written by `issue`
+    fn parse<T: FromStr>(row: &Row, index: usize) -> rusqlite::Result<T> {
+        let value: String = row.get(index)?;
+        value.parse().map_err(|_| {
+            rusqlite::Error::FromSqlConversionFailure(
+                index,
+                rusqlite::types::Type::Text,
+                format!("invalid value {}", value).into(),
+            )
+        })
+    }
+
+    Ok(AuditEntry {
+        id: row.get::<_, i64>(0)? as u64,
+        voucher: VoucherRecord {
+            signed_at: row.get::<_, i64>(1)? as u64,
+            request_id: row.get(2)?,
+            proposal_id: row.get(3)?,
+            boost_id: row.get(4)?,
+            chain_id: row.get(5)?,
+            verifying_contract: parse(row, 6)?,
+            recipient: parse(row, 7)?,
+            amount: row.get(8)?,
+            digest: parse(row, 9)?,
+            signature: row.get(10)?,
+            scores_state: row.get(11)?,
+            voting_power: row.get(12)?,
+            seed: match row.get::<_, Option<String>>(13)? {
+                Some(_) => Some(parse(row, 13)?),
+                None => None,
+            },
+        },
+    })
+}
+
+pub async fn handle_audit_vouchers(
+    Extension(state): Extension<State>,
+    headers: HeaderMap,
+    Json(p): Json<Value>,
+) -> Result<impl IntoResponse, ServerError> {
+    authorize(&state, &headers)?;
+    let mut filter: AuditFilter = parse_request(p)?;
+    filter.limit = Some(filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT));
+    Ok(Json(state.audit.entries(&filter)?))
+}
+
+pub fn audit_to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = String::from(
+        "id,signed_at,request_id,proposal_id,boost_id,chain_id,verifying_contract,recipient,\
+        amount,digest,signature,scores_state,voting_power,seed\n",
+    );
+    for AuditEntry { id, voucher: v } in entries {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{:?},{:?},{},{:?},{},{},{},{}\n",
+            id,
+            v.signed_at,
+            v.request_id,
+            v.proposal_id,
+            v.boost_id,
+            v.chain_id,
+            v.verifying_contract,
+            v.recipient,
+            v.amount,
+            v.digest,
+            v.signature,
+            v.scores_state,
+            v.voting_power,
+            v.seed.map(|seed| format!("{:?}", seed)).unwrap_or_default(),
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voucher(boost_id: &str, recipient: Address) -> VoucherRecord {
+        VoucherRecord {
+            signed_at: 1_700_000_000,
+            request_id: "5eed".to_string(),
+            proposal_id: format!("0x{}", "1".repeat(64)),
+            boost_id: boost_id.to_string(),
+            chain_id: "11155111".to_string(),
+            verifying_contract: Address::random(),
+            recipient,
+            amount: "1000".to_string(),
+            digest: H256::random(),
+            signature: "0x1234".to_string(),
+            scores_state: "final".to_string(),
+            voting_power: "12.5".to_string(),
+            seed: None,
+        }
+    }
+
+    fn issue(log: &AuditLog, voucher: &VoucherRecord) -> Result<AuditEntry, ServerError> {
+        log.issue(&voucher.claim_key(), || Ok(voucher.clone()))
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let log = AuditLog::in_memory().unwrap();
+        let (alice, bob) = (Address::random(), Address::random());
+        let first = voucher("1", alice);
+        let second = VoucherRecord {
+            seed: Some(H256::random()),
+            ..voucher("2", bob)
+        };
+        assert_eq!(issue(&log, &first).unwrap().id, 1);
+        assert_eq!(issue(&log, &second).unwrap().id, 2);
+
+        let all = log.entries(&AuditFilter::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[0].id, &all[0].voucher), (1, &first));
+        assert_eq!((all[1].id, &all[1].voucher), (2, &second));
+
+        // Recipients match whatever their case
+        let filter = AuditFilter {
+            recipient: Some(ethers::utils::to_checksum(&bob, None)),
+            ..Default::default()
+        };
+        assert_eq!(log.entries(&filter).unwrap(), vec![all[1].clone()]);
+
+        let filter = AuditFilter {
+            boost_id: Some("1".to_string()),
+            chain_id: Some("11155111".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(log.entries(&filter).unwrap(), vec![all[0].clone()]);
+
+        let page = AuditFilter {
+            after: Some(1),
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(log.entries(&page).unwrap(), vec![all[1].clone()]);
+
+        let filter = AuditFilter {
+            recipient: Some("0x12".to_string()),
+            ..Default::default()
+        };
+        assert!(log.entries(&filter).is_err());
+    }
+
+    #[test]
+    fn test_issue_once_per_claim() {
+        let log = AuditLog::in_memory().unwrap();
+        let first = voucher("1", Address::random());
+        assert_eq!(log.issued(&first.claim_key()).unwrap(), None);
+        let issued = issue(&log, &first).unwrap();
+        assert_eq!(
+            log.issued(&first.claim_key()).unwrap(),
+            Some(issued.clone())
+        );
+
+        // A recomputed amount does not get a second voucher
+        let recomputed = VoucherRecord {
+            amount: "2000".to_string(),
+            signature: "0x5678".to_string(),
+            ..first.clone()
+        };
+        assert_eq!(issue(&log, &recomputed).unwrap(), issued);
+        // Nor is it signed: only the first request of a claim signs its voucher
+        let signed = log
+            .issue(&first.claim_key(), || panic!("signed twice"))
+            .unwrap();
+        assert_eq!(signed, issued);
+        let other = voucher("1", Address::random());
+        assert!(log
+            .issue(&other.claim_key(), || Ok(voucher("2", other.recipient)))
+            .is_err());
+        assert_eq!(log.entries(&AuditFilter::default()).unwrap().len(), 1);
+
+        // Another recipient, boost, chain or contract is another claim
+        let claims = [
+            voucher("1", Address::random()),
+            voucher("2", first.recipient),
+            VoucherRecord {
+                chain_id: "1".to_string(),
+                ..first.clone()
+            },
+            VoucherRecord {
+                verifying_contract: Address::random(),
+                ..first.clone()
+            },
+        ];
+        for claim in &claims {
+            assert_ne!(issue(&log, claim).unwrap(), issued);
+        }
+        assert_eq!(log.entries(&AuditFilter::default()).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_append_only() {
+        let log = AuditLog::in_memory().unwrap();
+        issue(&log, &voucher("1", Address::random())).unwrap();
+
+        let conn = log.conn.lock().unwrap();
+        for table in ["vouchers", "issued"] {
+            assert!(conn
+                .execute(&format!("UPDATE {} SET boost_id = '2'", table), [])
+                .is_err());
+            assert!(conn.execute(&format!("DELETE FROM {}", table), []).is_err());
+        }
+    }
+
+    #[test]
+    fn test_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!("audit-{}.sqlite", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let record = voucher("1", Address::random());
+        let issued = issue(&AuditLog::open(path).unwrap(), &record).unwrap();
+        // The ledger outlives a restart: the claim is not issued twice
+        let reopened = AuditLog::open(path).unwrap();
+        assert_eq!(issue(&reopened, &record).unwrap(), issued);
+        assert_eq!(reopened.entries(&AuditFilter::default()).unwrap().len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_to_csv() {
+        let record = voucher("1", Address::random());
+        let csv = audit_to_csv(&[AuditEntry {
+            id: 1,
+            voucher: record.clone(),
+        }]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id,signed_at,request_id"));
+        assert!(lines[1].starts_with("1,1700000000,5eed,"));
+        assert!(lines[1].ends_with(",final,12.5,"));
+    }
+}
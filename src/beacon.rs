@@ -0,0 +1,122 @@
+use crate::metrics;
+use crate::ServerError;
+use crate::{BEACONCHAIN_API_KEY, EPOCH_URL, SLOT_URL};
+use async_trait::async_trait;
+use serde_json::Value;
+
+const FIRST_MERGED_SLOT: u64 = 4700013;
+const FIRST_MERGED_SLOT_TIMESTAMP: u64 = 1663224179;
+
+/// Source of onchain randomness, used to draw lottery winners.
+#[async_trait]
+pub trait RandomnessBeacon: std::fmt::Debug + Send + Sync {
+    /// Returns the (hex encoded) randao reveal of the finalized epoch containing `timestamp`.
+    async fn randao_reveal(&self, timestamp: u64) -> Result<String, ServerError>;
+}
+
+/// Randomness beacon backed by the REST API of an eth2 node (or beaconcha.in).
+#[derive(Debug, Clone)]
+pub struct Beaconchain {
+    client: reqwest::Client,
+    slot_url: String,
+    epoch_url: String,
+    api_key: String,
+}
+
+impl Beaconchain {
+    pub fn new(client: reqwest::Client, slot_url: &str, epoch_url: &str, api_key: &str) -> Self {
+        Self {
+            client,
+            slot_url: slot_url.to_string(),
+            epoch_url: epoch_url.to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    /// Creates a beacon using the urls and api key set in the environment.
+    pub fn from_env(client: reqwest::Client) -> Self {
+        Self::new(
+            client,
+            SLOT_URL.as_str(),
+            EPOCH_URL.as_str(),
+            BEACONCHAIN_API_KEY.as_str(),
+        )
+    }
+}
+
+// This function tries to map a timestamp to a randao reveal.
+// A randao reveal is a source of randomness provided by the beacon chain. Each epoch has a randao reveal.
+// This function tries to find the epoch that contains the timestamp and returns the randao reveal of that epoch.
+// It does so by following these steps:
+// Step 1: Find the closest slot corresponding to the timestamp (round up to nearest multiple of 12, because slots are 12 seconds long).
+// Step 2: Query the slot url to get the corresponding slot, and extract its epoch.
+// Step 3: Query the epoch url to get the corresponding epoch.
+// Step 4: Ensure the epoch is finalized, and return its randao reveal.
+#[async_trait]
+impl RandomnessBeacon for Beaconchain {
+    async fn randao_reveal(&self, timestamp: u64) -> Result<String, ServerError> {
+        metrics::observe_upstream("beacon", "randao_reveal", async {
+            // Step 1
+            let elapsed = timestamp - FIRST_MERGED_SLOT_TIMESTAMP;
+            let rounded_elapsed = elapsed + (12 - elapsed % 12);
+            let elapsed_slots = rounded_elapsed / 12;
+            let nearest_slot = FIRST_MERGED_SLOT + elapsed_slots;
+
+            // Step 2
+            let slot_url = format!("{}{}?apikey={}", self.slot_url, nearest_slot, self.api_key);
+            let slot: Value = self.client.get(&slot_url).send().await?.json().await?;
+            tracing::info!(?slot);
+            let epoch = slot["data"]["epoch"]
+                .as_u64()
+                .ok_or_else(|| upstream_error("failed to parse epoch"))?;
+            tracing::info!(?epoch);
+
+            // Step 3
+            let epoch_url = format!("{}{}?apikey={}", self.epoch_url, epoch, self.api_key);
+            let epoch_details: Value = self.client.get(&epoch_url).send().await?.json().await?;
+
+            // Step 4
+            let finalized = epoch_details["data"]["finalized"]
+                .as_bool()
+                .ok_or_else(|| upstream_error("finalized is not a boolean"))?;
+            if !finalized {
+                return Err(upstream_error("epoch is not finalized"));
+            }
+
+            let randao_reveal = slot["data"]["randaoreveal"]
+                .as_str()
+                .ok_or_else(|| upstream_error("randao_reveal is not a string"))?
+                .to_string();
+
+            tracing::info!(?randao_reveal);
+            Ok(randao_reveal)
+        })
+        .await
+    }
+}
+
+fn upstream_error(msg: &str) -> ServerError {
+    ServerError::UpstreamUnavailable(format!("beacon: {}", msg))
+}
+
+/// Randomness beacon returning the same randao reveal for every timestamp.
+/// Useful for tests, or to embed the guard without an eth2 node.
+#[derive(Debug, Clone)]
+pub struct InMemoryBeacon {
+    randao_reveal: String,
+}
+
+impl InMemoryBeacon {
+    pub fn new(randao_reveal: &str) -> Self {
+        Self {
+            randao_reveal: randao_reveal.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl RandomnessBeacon for InMemoryBeacon {
+    async fn randao_reveal(&self, _timestamp: u64) -> Result<String, ServerError> {
+        Ok(self.randao_reveal.clone())
+    }
+}
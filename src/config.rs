@@ -0,0 +1,315 @@
+//! Guard configuration: the chains the guard signs vouchers for, and the boost contracts
+//! deployed on each of them.
+//!
+//! The configuration is read from the TOML file pointed by `CONFIG_PATH`. When `CONFIG_PATH` is
+//! not set, it falls back to the legacy environment variables (`MAINNET_SUBGRAPH_URL`,
+//! `BOOST_NAME`, ...), which describe mainnet, sepolia, polygon and base.
+
+use crate::tokens;
+use crate::ServerError;
+use ethers::types::Address;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub chains: Vec<ChainConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    // Boost contracts deployed on this chain. Older deployments are kept so that their boosts
+    // can still be claimed.
+    pub deployments: Vec<DeploymentConfig>,
+    // Tokens the guard refuses to sign vouchers for
+    #[serde(default)]
+    pub disabled_tokens: HashSet<Address>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeploymentConfig {
+    // Url of the subgraph indexing this boost contract
+    pub subgraph_url: String,
+    // The onchain boost address, used as the EIP712 verifying contract
+    pub verifying_contract: Address,
+    // EIP712 domain name and version, should match the onchain ones
+    pub eip712_name: String,
+    pub eip712_version: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read the config: {}", err),
+            ConfigError::Parse(err) => write!(f, "failed to parse the config: {}", err),
+            ConfigError::Invalid(msg) => write!(f, "invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the configuration from `CONFIG_PATH`, or from the legacy environment variables.
+    pub fn load() -> Result<Self, ConfigError> {
+        match env::var("CONFIG_PATH") {
+            Ok(path) => Self::from_file(&path),
+            Err(_) => Self::from_env(),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(content).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Builds the configuration from the legacy environment variables, where the EIP712 domain
+    /// is shared by every chain.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let var = |name: &str| {
+            env::var(name).map_err(|_| {
+                ConfigError::Invalid(format!(
+                    "Please add {} to your environment or .env file, or set CONFIG_PATH",
+                    name
+                ))
+            })
+        };
+
+        let verifying_contract = var("VERIFYING_CONTRACT")?
+            .parse()
+            .map_err(|_| ConfigError::Invalid("VERIFYING_CONTRACT is not an address".into()))?;
+        let eip712_name = var("BOOST_NAME")?;
+        let eip712_version = var("BOOST_VERSION")?;
+        let disabled_tokens = tokens::create_disabled_token_list();
+
+        let chains = [
+            (1, "MAINNET_SUBGRAPH_URL"),
+            (11155111, "SEPOLIA_SUBGRAPH_URL"),
+            (137, "POLYGON_SUBGRAPH_URL"),
+            (8453, "BASE_SUBGRAPH_URL"),
+        ]
+        .into_iter()
+        .map(|(chain_id, url_var)| {
+            Ok(ChainConfig {
+                chain_id,
+                deployments: vec![DeploymentConfig {
+                    subgraph_url: var(url_var)?,
+                    verifying_contract,
+                    eip712_name: eip712_name.clone(),
+                    eip712_version: eip712_version.clone(),
+                }],
+                disabled_tokens: disabled_tokens
+                    .iter()
+                    .filter(|(_, chain)| *chain == chain_id.to_string())
+                    .map(|(token, _)| *token)
+                    .collect(),
+            })
+        })
+        .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        let config = Config { chains };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.chains.is_empty() {
+            return Err(ConfigError::Invalid("no chain configured".to_string()));
+        }
+
+        let mut chain_ids = HashSet::new();
+        for chain in &self.chains {
+            let invalid =
+                |msg: &str| ConfigError::Invalid(format!("chain {}: {}", chain.chain_id, msg));
+
+            if !chain_ids.insert(chain.chain_id) {
+                return Err(invalid("configured more than once"));
+            }
+            if chain.deployments.is_empty() {
+                return Err(invalid("no deployment configured"));
+            }
+
+            let mut contracts = HashSet::new();
+            for deployment in &chain.deployments {
+                if !contracts.insert(deployment.verifying_contract) {
+                    return Err(invalid("verifying_contract configured more than once"));
+                }
+                match reqwest::Url::parse(&deployment.subgraph_url) {
+                    Ok(url) if url.scheme() == "http" || url.scheme() == "https" => (),
+                    _ => return Err(invalid("subgraph_url is not an http(s) url")),
+                }
+                if deployment.verifying_contract.is_zero() {
+                    return Err(invalid("verifying_contract is the zero address"));
+                }
+                if deployment.eip712_name.is_empty() || deployment.eip712_version.is_empty() {
+                    return Err(invalid("eip712_name and eip712_version can not be empty"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configuration of chain `chain_id`.
+    pub fn chain(&self, chain_id: &str) -> Result<&ChainConfig, ServerError> {
+        self.chains
+            .iter()
+            .find(|chain| chain.chain_id.to_string() == chain_id)
+            .ok_or_else(|| ServerError::InvalidInput(format!("unsupported chain {}", chain_id)))
+    }
+}
+
+impl ChainConfig {
+    /// Returns the deployment whose boost contract is `verifying_contract`.
+    pub fn deployment(
+        &self,
+        verifying_contract: &Address,
+    ) -> Result<&DeploymentConfig, ServerError> {
+        self.deployments
+            .iter()
+            .find(|deployment| deployment.verifying_contract == *verifying_contract)
+            .ok_or_else(|| {
+                ServerError::Internal(format!(
+                    "no deployment of {:?} on chain {}",
+                    verifying_contract, self.chain_id
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigError};
+
+    const CONFIG: &str = r#"
+        [[chains]]
+        chain_id = 1
+        disabled_tokens = ["0xdac17f958d2ee523a2206206994597c13d831ec7"]
+
+        [[chains.deployments]]
+        subgraph_url = "https://subgrapher.snapshot.org/subgraph/arbitrum/mainnet"
+        verifying_contract = "0x8E8913197114c911F13cfBfCBBD138C1DC74B964"
+        eip712_name = "boost"
+        eip712_version = "0.1.0"
+
+        [[chains.deployments]]
+        subgraph_url = "https://subgrapher.snapshot.org/subgraph/arbitrum/mainnet-v2"
+        verifying_contract = "0x3a18420C0646CC8e6D46E43d792335AeCB657fd0"
+        eip712_name = "boost"
+        eip712_version = "0.2.0"
+
+        [[chains]]
+        chain_id = 10
+
+        [[chains.deployments]]
+        subgraph_url = "https://subgrapher.snapshot.org/subgraph/arbitrum/optimism"
+        verifying_contract = "0x8E8913197114c911F13cfBfCBBD138C1DC74B964"
+        eip712_name = "boost"
+        eip712_version = "0.1.0"
+    "#;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        assert_eq!(config.chains.len(), 2);
+
+        let mainnet = config.chain("1").unwrap();
+        assert_eq!(mainnet.disabled_tokens.len(), 1);
+        assert!(mainnet.disabled_tokens.contains(
+            &"0xdac17f958d2ee523a2206206994597c13d831ec7"
+                .parse()
+                .unwrap()
+        ));
+
+        let v2 = mainnet
+            .deployment(
+                &"0x3a18420C0646CC8e6D46E43d792335AeCB657fd0"
+                    .parse()
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(v2.eip712_version, "0.2.0");
+
+        let optimism = config.chain("10").unwrap();
+        assert!(optimism.disabled_tokens.is_empty());
+        assert!(optimism
+            .deployment(
+                &"0x3a18420C0646CC8e6D46E43d792335AeCB657fd0"
+                    .parse()
+                    .unwrap()
+            )
+            .is_err());
+
+        assert!(config.chain("137").is_err());
+    }
+
+    #[test]
+    fn test_example_config() {
+        Config::from_toml(include_str!("../config.example.toml")).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let duplicated = CONFIG.replace("chain_id = 10", "chain_id = 1");
+        assert!(matches!(
+            Config::from_toml(&duplicated),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let duplicated_contract = CONFIG.replace(
+            "0x3a18420C0646CC8e6D46E43d792335AeCB657fd0",
+            "0x8E8913197114c911F13cfBfCBBD138C1DC74B964",
+        );
+        assert!(matches!(
+            Config::from_toml(&duplicated_contract),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let bad_url = CONFIG.replace(
+            "https://subgrapher.snapshot.org/subgraph/arbitrum/optimism",
+            "subgraph",
+        );
+        assert!(matches!(
+            Config::from_toml(&bad_url),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let zero_contract = CONFIG.replace(
+            "0x8E8913197114c911F13cfBfCBBD138C1DC74B964",
+            "0x0000000000000000000000000000000000000000",
+        );
+        assert!(matches!(
+            Config::from_toml(&zero_contract),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            Config::from_toml("chains = []"),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let unknown_field = CONFIG.replace("eip712_name", "name");
+        assert!(matches!(
+            Config::from_toml(&unknown_field),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+//! Allocation of the pool of a boost between its eligible votes.
+//!
+//! Rewards are computed for every vote at once, so that their sum can be checked against the
+//! pool: the total never exceeds `pool_size`. The dust left over by rounding down is handed out
+//! one unit at a time to the largest holders, so that the whole pool is distributed unless the
+//! reward limit prevents it.
+
+use crate::routes::Vote;
+use crate::voting_power::{mul_div, VotingPower};
+use crate::ServerError;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// How the pool is shared between the votes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    // Every vote gets the same share
+    Even,
+    // Shares are proportional to the voting power
+    VotingPower,
+}
+
+/// Rewards of every vote of a boost.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    rewards: HashMap<Address, U256>,
+    total: U256,
+}
+
+impl Allocation {
+    /// Reward of `voter`, or `None` if their vote is not part of the allocation.
+    pub fn reward(&self, voter: &Address) -> Option<U256> {
+        self.rewards.get(voter).copied()
+    }
+
+    /// Sum of all the rewards, at most the pool size.
+    pub fn total(&self) -> U256 {
+        self.total
+    }
+
+    /// Every voter along with their reward, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Address, U256)> + '_ {
+        self.rewards.iter().map(|(voter, reward)| (*voter, *reward))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rewards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rewards.is_empty()
+    }
+}
+
+/// Splits `pool_size` between `votes`. No vote gets more than `limit`: the largest holders are
+/// capped first and what they leave is shared between the others.
+pub fn allocate(
+    votes: &[Vote],
+    pool_size: U256,
+    weighting: Weighting,
+    limit: Option<U256>,
+) -> Result<Allocation, ServerError> {
+    // Largest holders first; ties are broken by address so that the dust is assigned
+    // deterministically
+    let mut votes: Vec<&Vote> = votes.iter().collect();
+    votes.sort_by_key(|vote| (Reverse(vote.voting_power), vote.voter));
+
+    let weight = |vote: &Vote| match weighting {
+        Weighting::Even => VotingPower::ONE.raw(),
+        Weighting::VotingPower => vote.voting_power.raw(),
+    };
+
+    let mut remaining_pool = pool_size;
+    let mut remaining_weight = votes
+        .iter()
+        .fold(U256::zero(), |acc, vote| acc + weight(vote));
+    let mut rewards = Vec::with_capacity(votes.len());
+
+    // Once a holder is under the limit, every smaller holder is too: what the capped holders
+    // leave only increases the share of the others
+    if let Some(limit) = limit {
+        for vote in &votes {
+            if mul_div(remaining_pool, weight(vote), remaining_weight) < limit {
+                break;
+            }
+            rewards.push(limit);
+            remaining_pool -= limit;
+            remaining_weight -= weight(vote);
+        }
+    }
+
+    let uncapped = &votes[rewards.len()..];
+    let mut shares: Vec<U256> = uncapped
+        .iter()
+        .map(|vote| mul_div(remaining_pool, weight(vote), remaining_weight))
+        .collect();
+
+    // Every share is rounded down by less than one unit, so the dust is smaller than the number of
+    // uncapped holders: one more unit for the largest of them, which keeps them within the limit
+    let distributed = shares.iter().fold(U256::zero(), |acc, share| acc + share);
+    let mut dust = remaining_pool - distributed;
+    for (share, vote) in shares.iter_mut().zip(uncapped) {
+        if dust.is_zero() {
+            break;
+        }
+        if !weight(vote).is_zero() {
+            *share += U256::one();
+            dust -= U256::one();
+        }
+    }
+    rewards.extend(shares);
+
+    let total = rewards
+        .iter()
+        .fold(U256::zero(), |acc, reward| acc + reward);
+    if total > pool_size {
+        return Err(ServerError::Internal(format!(
+            "allocated {} but the pool only holds {}",
+            total, pool_size
+        )));
+    }
+
+    Ok(Allocation {
+        rewards: votes
+            .into_iter()
+            .map(|vote| vote.voter)
+            .zip(rewards)
+            .collect(),
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn vote(i: u64, voting_power: &str) -> Vote {
+        Vote {
+            voter: Address::from_low_u64_be(i),
+            voting_power: voting_power.parse().unwrap(),
+        }
+    }
+
+    fn ether(amount: u64) -> U256 {
+        U256::exp10(18) * amount
+    }
+
+    #[test]
+    fn test_even_dust_goes_to_largest_holders() {
+        let votes = vec![vote(1, "1"), vote(2, "3"), vote(3, "2")];
+        let allocation = allocate(&votes, U256::from(101), Weighting::Even, None).unwrap();
+
+        assert_eq!(allocation.reward(&votes[1].voter), Some(U256::from(34)));
+        assert_eq!(allocation.reward(&votes[2].voter), Some(U256::from(34)));
+        assert_eq!(allocation.reward(&votes[0].voter), Some(U256::from(33)));
+        assert_eq!(allocation.total(), U256::from(101));
+    }
+
+    #[test]
+    fn test_weighted() {
+        let votes = vec![vote(1, "1"), vote(2, "1"), vote(3, "1")];
+        let allocation = allocate(&votes, U256::from(100), Weighting::VotingPower, None).unwrap();
+
+        // Same voting power: the tie is broken by address
+        assert_eq!(allocation.reward(&votes[0].voter), Some(U256::from(34)));
+        assert_eq!(allocation.reward(&votes[1].voter), Some(U256::from(33)));
+        assert_eq!(allocation.reward(&votes[2].voter), Some(U256::from(33)));
+        assert_eq!(allocation.total(), U256::from(100));
+        assert_eq!(allocation.reward(&Address::from_low_u64_be(4)), None);
+    }
+
+    #[test]
+    fn test_weighted_limit() {
+        // Every voter reaches the limit: what is left stays in the pool
+        let votes = vec![
+            vote(1, "38.5"),
+            vote(2, "25"),
+            vote(3, "20"),
+            vote(4, "15"),
+            vote(5, "1"),
+            vote(6, "0.5"),
+        ];
+        let limit = ether(10);
+        let allocation = allocate(&votes, ether(200), Weighting::VotingPower, Some(limit)).unwrap();
+        for vote in &votes {
+            assert_eq!(allocation.reward(&vote.voter), Some(limit));
+        }
+        assert_eq!(allocation.total(), ether(60));
+
+        // The small voters share what the capped ones leave
+        let votes = vec![vote(1, "90"), vote(2, "9"), vote(3, "1")];
+        let limit = ether(150);
+        let allocation = allocate(&votes, ether(200), Weighting::VotingPower, Some(limit)).unwrap();
+        assert_eq!(allocation.reward(&votes[0].voter), Some(limit));
+        assert_eq!(allocation.reward(&votes[1].voter), Some(ether(45)));
+        assert_eq!(allocation.reward(&votes[2].voter), Some(ether(5)));
+        assert_eq!(allocation.total(), ether(200));
+
+        // What the capped voters leave makes the small ones reach the limit too
+        let limit = ether(40);
+        let allocation = allocate(&votes, ether(200), Weighting::VotingPower, Some(limit)).unwrap();
+        for vote in &votes {
+            assert_eq!(allocation.reward(&vote.voter), Some(limit));
+        }
+
+        // A single voter gets at most the limit
+        let votes = vec![vote(1, "91")];
+        let limit = ether(110);
+        let allocation = allocate(&votes, ether(200), Weighting::VotingPower, Some(limit)).unwrap();
+        assert_eq!(allocation.reward(&votes[0].voter), Some(limit));
+    }
+
+    #[test]
+    fn test_empty() {
+        let allocation = allocate(&[], ether(200), Weighting::Even, None).unwrap();
+        assert!(allocation.is_empty());
+        assert_eq!(allocation.total(), U256::zero());
+
+        // No voting power at all: nothing to share
+        let votes = vec![vote(1, "0"), vote(2, "0")];
+        let allocation = allocate(&votes, ether(200), Weighting::VotingPower, None).unwrap();
+        assert_eq!(allocation.len(), 2);
+        assert_eq!(allocation.total(), U256::zero());
+    }
+
+    fn votes_strategy() -> impl Strategy<Value = Vec<Vote>> {
+        // Voting powers from 0 up to 10^12, with 18 decimals
+        prop::collection::vec(0..=1_000_000_000_000_000_000_000_000_000_000_u128, 1..50).prop_map(
+            |voting_powers| {
+                voting_powers
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, vp)| Vote {
+                        voter: Address::from_low_u64_be(i as u64 + 1),
+                        voting_power: VotingPower::from_raw(U256::from(vp)),
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    fn weighting_strategy() -> impl Strategy<Value = Weighting> {
+        prop_oneof![Just(Weighting::Even), Just(Weighting::VotingPower)]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_never_exceeds_pool(
+            votes in votes_strategy(),
+            pool_size in any::<u128>(),
+            weighting in weighting_strategy(),
+            limit in prop::option::of(1..=u128::MAX),
+        ) {
+            let pool_size = U256::from(pool_size);
+            let limit = limit.map(U256::from);
+            let allocation = allocate(&votes, pool_size, weighting, limit).unwrap();
+
+            let sum = votes
+                .iter()
+                .map(|vote| allocation.reward(&vote.voter).unwrap())
+                .fold(U256::zero(), |acc, reward| acc + reward);
+            prop_assert_eq!(sum, allocation.total());
+            prop_assert!(sum <= pool_size);
+            if let Some(limit) = limit {
+                for vote in &votes {
+                    prop_assert!(allocation.reward(&vote.voter).unwrap() <= limit);
+                }
+            }
+        }
+
+        #[test]
+        fn prop_whole_pool_is_distributed(
+            votes in votes_strategy(),
+            pool_size in any::<u128>(),
+            weighting in weighting_strategy(),
+        ) {
+            let pool_size = U256::from(pool_size);
+            let allocation = allocate(&votes, pool_size, weighting, None).unwrap();
+
+            let has_weight = weighting == Weighting::Even
+                || votes.iter().any(|vote| !vote.voting_power.is_zero());
+            if has_weight {
+                prop_assert_eq!(allocation.total(), pool_size);
+            }
+        }
+
+        #[test]
+        fn prop_larger_holders_get_more(
+            votes in votes_strategy(),
+            pool_size in any::<u128>(),
+            weighting in weighting_strategy(),
+            limit in prop::option::of(1..=u128::MAX),
+        ) {
+            let allocation =
+                allocate(&votes, U256::from(pool_size), weighting, limit.map(U256::from)).unwrap();
+
+            let mut sorted = votes.clone();
+            sorted.sort_by_key(|vote| (Reverse(vote.voting_power), vote.voter));
+            for pair in sorted.windows(2) {
+                let larger = allocation.reward(&pair[0].voter).unwrap();
+                let smaller = allocation.reward(&pair[1].voter).unwrap();
+                prop_assert!(larger >= smaller);
+                // Equal shares only differ by the dust
+                if weighting == Weighting::Even || pair[0].voting_power == pair[1].voting_power {
+                    prop_assert!(larger - smaller <= U256::one());
+                }
+            }
+        }
+
+        #[test]
+        fn prop_deterministic(
+            votes in votes_strategy(),
+            pool_size in any::<u128>(),
+            weighting in weighting_strategy(),
+        ) {
+            let allocation = allocate(&votes, U256::from(pool_size), weighting, None).unwrap();
+            let mut reversed = votes.clone();
+            reversed.reverse();
+            prop_assert_eq!(
+                allocate(&reversed, U256::from(pool_size), weighting, None).unwrap(),
+                allocation
+            );
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use crate::metrics;
+use crate::routes::{ProposalInfo, Vote, VoteWithChoice};
+use crate::voting_power::VotingPower;
+use crate::ServerError;
+use async_trait::async_trait;
+use ethers::types::Address;
+use mysql_async::prelude::{FromRow, Queryable};
+use mysql_async::Row;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Read access to the hub's proposals and votes.
+#[async_trait]
+pub trait HubStore: std::fmt::Debug + Send + Sync {
+    /// Returns the proposal with the given id, if any.
+    async fn proposal(&self, proposal_id: &str) -> Result<Option<ProposalInfo>, ServerError>;
+
+    /// Returns the vote cast by `voter_address` on the given proposal, if any.
+    async fn vote(
+        &self,
+        voter_address: &str,
+        proposal_id: &str,
+    ) -> Result<Option<VoteWithChoice>, ServerError>;
+
+    /// Returns the ids of the proposals `voter_address` voted on.
+    async fn voted_proposals(&self, voter_address: &str) -> Result<Vec<String>, ServerError>;
+
+    /// Returns the votes of a proposal, sorted by voting power (highest first).
+    /// If `choice` is set, only the votes for this choice are returned.
+    async fn votes(
+        &self,
+        proposal_id: &str,
+        choice: Option<usize>,
+    ) -> Result<Vec<Vote>, ServerError>;
+}
+
+/// Hub store backed by the hub's MySQL database.
+#[derive(Debug, Clone)]
+pub struct MysqlHub {
+    pool: mysql_async::Pool,
+}
+
+impl MysqlHub {
+    pub fn new(pool: mysql_async::Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HubStore for MysqlHub {
+    async fn proposal(&self, proposal_id: &str) -> Result<Option<ProposalInfo>, ServerError> {
+        metrics::observe_upstream("hub", "proposal", async {
+            let mut conn = self.pool.get_conn().await?;
+
+            let query =
+                "SELECT id, choices, end, privacy, scores, scores_total, scores_state, type, votes
+                FROM proposals
+                WHERE id = ?";
+
+            let proposal_info: Option<ProposalInfo> =
+                conn.exec_first(query, (proposal_id,)).await?;
+
+            conn.disconnect().await?;
+            Ok(proposal_info)
+        })
+        .await
+    }
+
+    async fn vote(
+        &self,
+        voter_address: &str,
+        proposal_id: &str,
+    ) -> Result<Option<VoteWithChoice>, ServerError> {
+        metrics::observe_upstream("hub", "vote", async {
+            let mut conn = self.pool.get_conn().await?;
+
+            let query = "SELECT voter, vp, choice
+                FROM votes
+                WHERE proposal = ?
+                AND voter = ?
+                ORDER BY vp DESC;";
+
+            let row: Option<(String, String, String)> =
+                conn.exec_first(query, (proposal_id, voter_address)).await?;
+
+            conn.disconnect().await?;
+
+            row.map(|(_voter, voting_power, choice)| {
+                Ok(VoteWithChoice {
+                    voter: Address::from_str(voter_address)
+                        .map_err(|e| ServerError::InvalidInput(e.to_string()))?,
+                    voting_power: parse_voting_power(&voting_power)?,
+                    choice,
+                })
+            })
+            .transpose()
+        })
+        .await
+    }
+
+    async fn voted_proposals(&self, voter_address: &str) -> Result<Vec<String>, ServerError> {
+        metrics::observe_upstream("hub", "voted_proposals", async {
+            let mut conn = self.pool.get_conn().await?;
+
+            let query = "SELECT DISTINCT proposal
+                FROM votes
+                WHERE voter = ?;";
+
+            let proposals: Vec<String> = conn.exec(query, (voter_address,)).await?;
+
+            conn.disconnect().await?;
+
+            Ok(proposals)
+        })
+        .await
+    }
+
+    async fn votes(
+        &self,
+        proposal_id: &str,
+        choice: Option<usize>,
+    ) -> Result<Vec<Vote>, ServerError> {
+        metrics::observe_upstream("hub", "votes", async {
+            let mut conn = self.pool.get_conn().await?;
+
+            let rows: Vec<(String, String)> = if let Some(choice) = choice {
+                let query = "SELECT voter, vp
+                    FROM votes
+                    WHERE proposal = ?
+                    AND choice = ?
+                    ORDER BY vp DESC;";
+                conn.exec(query, (proposal_id, choice)).await?
+            } else {
+                let query = "SELECT voter, vp
+                    FROM votes
+                    WHERE proposal = ?
+                    ORDER BY vp DESC;";
+                conn.exec(query, (proposal_id,)).await?
+            };
+
+            conn.disconnect().await?;
+
+            rows.into_iter()
+                .map(|(voter, vp)| {
+                    Ok(Vote {
+                        voter: Address::from_str(&voter)
+                            .map_err(|e| ServerError::Internal(format!("hub: {}", e)))?,
+                        voting_power: parse_voting_power(&vp)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+impl FromRow for ProposalInfo {
+    fn from_row(row: Row) -> Self
+    where
+        Self: Sized,
+    {
+        Self::from_row_opt(row).unwrap()
+    }
+
+    fn from_row_opt(row: Row) -> Result<Self, mysql_async::FromRowError>
+    where
+        Self: Sized,
+    {
+        let id: String = row.get("id").unwrap();
+        let end: u64 = row.get("end").unwrap();
+        let privacy: String = row.get("privacy").unwrap();
+        let scores_str: String = row.get("scores").unwrap();
+        let scores_state: String = row.get("scores_state").unwrap();
+        let score_str: String = row.get("scores_total").unwrap();
+        let type_: String = row.get("type").unwrap();
+        let num_votes: u64 = row.get("votes").unwrap();
+
+        let (Ok(score), Ok(scores_by_choice)) = (score_str.parse(), parse_scores(&scores_str))
+        else {
+            return Err(mysql_async::FromRowError(row));
+        };
+
+        Ok(ProposalInfo {
+            id,
+            type_,
+            score,
+            scores_by_choice,
+            scores_state,
+            end,
+            privacy,
+            num_votes,
+        })
+    }
+}
+
+/// Hub store kept in memory. Useful for tests, or to embed the guard without a database.
+#[derive(Debug, Default)]
+pub struct InMemoryHub {
+    proposals: RwLock<HashMap<String, ProposalInfo>>,
+    // Votes, indexed by proposal id
+    votes: RwLock<HashMap<String, Vec<VoteWithChoice>>>,
+}
+
+impl InMemoryHub {
+    pub fn insert_proposal(&self, proposal_info: ProposalInfo) {
+        self.proposals
+            .write()
+            .unwrap()
+            .insert(proposal_info.id.clone(), proposal_info);
+    }
+
+    /// Records a vote. A previous vote from the same voter on the same proposal gets replaced.
+    pub fn insert_vote(&self, proposal_id: &str, vote: VoteWithChoice) {
+        let mut votes = self.votes.write().unwrap();
+        let proposal_votes = votes.entry(proposal_id.to_string()).or_default();
+        proposal_votes.retain(|v| v.voter != vote.voter);
+        proposal_votes.push(vote);
+    }
+}
+
+#[async_trait]
+impl HubStore for InMemoryHub {
+    async fn proposal(&self, proposal_id: &str) -> Result<Option<ProposalInfo>, ServerError> {
+        Ok(self.proposals.read().unwrap().get(proposal_id).cloned())
+    }
+
+    async fn vote(
+        &self,
+        voter_address: &str,
+        proposal_id: &str,
+    ) -> Result<Option<VoteWithChoice>, ServerError> {
+        let voter = Address::from_str(voter_address)
+            .map_err(|e| ServerError::InvalidInput(e.to_string()))?;
+        Ok(self
+            .votes
+            .read()
+            .unwrap()
+            .get(proposal_id)
+            .and_then(|votes| votes.iter().find(|v| v.voter == voter))
+            .cloned())
+    }
+
+    async fn voted_proposals(&self, voter_address: &str) -> Result<Vec<String>, ServerError> {
+        let voter = Address::from_str(voter_address)
+            .map_err(|e| ServerError::InvalidInput(e.to_string()))?;
+        let mut proposals: Vec<String> = self
+            .votes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, votes)| votes.iter().any(|v| v.voter == voter))
+            .map(|(proposal_id, _)| proposal_id.clone())
+            .collect();
+
+        proposals.sort();
+        Ok(proposals)
+    }
+
+    async fn votes(
+        &self,
+        proposal_id: &str,
+        choice: Option<usize>,
+    ) -> Result<Vec<Vote>, ServerError> {
+        let choice = choice.map(|c| c.to_string());
+        let mut votes: Vec<Vote> = self
+            .votes
+            .read()
+            .unwrap()
+            .get(proposal_id)
+            .map(|votes| {
+                votes
+                    .iter()
+                    .filter(|v| choice.as_ref().is_none_or(|c| *c == v.choice))
+                    .map(|v| Vote {
+                        voter: v.voter,
+                        voting_power: v.voting_power,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        votes.sort_by_key(|v| std::cmp::Reverse(v.voting_power));
+        Ok(votes)
+    }
+}
+
+// The hub stores voting power as a DECIMAL column: it is read as a string to be parsed exactly
+fn parse_voting_power(vp: &str) -> Result<VotingPower, ServerError> {
+    vp.parse()
+        .map_err(|e| ServerError::Internal(format!("hub: {}", e)))
+}
+
+// Parses the JSON array of the scores of a proposal without going through f64
+fn parse_scores(scores: &str) -> Result<Vec<VotingPower>, ServerError> {
+    let scores = scores
+        .trim()
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| ServerError::Internal(format!("hub: invalid scores {:?}", scores)))?;
+
+    scores
+        .split(',')
+        .map(str::trim)
+        .filter(|score| !score.is_empty())
+        .map(parse_voting_power)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_scores, HubStore, InMemoryHub};
+    use crate::routes::VoteWithChoice;
+    use crate::voting_power::VotingPower;
+
+    const PROPOSAL_ID: &str = "0xc3beb923ad594240e964324c07b6ed0828687d149c3ef30085e8ca844cf11ee1";
+
+    #[tokio::test]
+    async fn test_in_memory_votes() {
+        let hub = InMemoryHub::default();
+        let small = VoteWithChoice {
+            voting_power: VotingPower::from(1),
+            choice: "1".to_string(),
+            ..Default::default()
+        };
+        let big = VoteWithChoice {
+            voting_power: VotingPower::from(3),
+            choice: "2".to_string(),
+            ..Default::default()
+        };
+        let medium = VoteWithChoice {
+            voting_power: VotingPower::from(2),
+            choice: "1".to_string(),
+            ..Default::default()
+        };
+        hub.insert_vote(PROPOSAL_ID, small.clone());
+        hub.insert_vote(PROPOSAL_ID, big.clone());
+        hub.insert_vote(PROPOSAL_ID, medium.clone());
+
+        // Votes should be sorted by voting power
+        let votes = hub.votes(PROPOSAL_ID, None).await.unwrap();
+        let voters: Vec<_> = votes.iter().map(|v| v.voter).collect();
+        assert_eq!(voters, vec![big.voter, medium.voter, small.voter]);
+
+        // Only votes for the requested choice should be returned
+        let votes = hub.votes(PROPOSAL_ID, Some(1)).await.unwrap();
+        let voters: Vec<_> = votes.iter().map(|v| v.voter).collect();
+        assert_eq!(voters, vec![medium.voter, small.voter]);
+
+        let vote = hub
+            .vote(&format!("{:?}", big.voter), PROPOSAL_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(vote.choice, "2");
+        assert!(hub
+            .vote(&format!("{:?}", big.voter), "0x00")
+            .await
+            .unwrap()
+            .is_none());
+
+        hub.insert_vote("0x00", big.clone());
+        assert_eq!(
+            hub.voted_proposals(&format!("{:?}", big.voter))
+                .await
+                .unwrap(),
+            vec!["0x00".to_string(), PROPOSAL_ID.to_string()]
+        );
+        assert_eq!(
+            hub.voted_proposals(&format!("{:?}", small.voter))
+                .await
+                .unwrap(),
+            vec![PROPOSAL_ID.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_scores() {
+        assert_eq!(
+            parse_scores("[60, 0.25, 1.2345678901234567e+21]").unwrap(),
+            vec![
+                VotingPower::from(60),
+                "0.25".parse().unwrap(),
+                "1234567890123456700000".parse().unwrap(),
+            ]
+        );
+        assert!(parse_scores("[]").unwrap().is_empty());
+        assert!(parse_scores("60, 40").is_err());
+        assert!(parse_scores("[60, -1]").is_err());
+    }
+}
@@ -1,17 +1,25 @@
 use axum::response::{IntoResponse, Response};
-use ethers::types::Address;
+use axum::Json;
 use hyper::http::StatusCode;
-use std::collections::HashSet;
+use std::sync::Arc;
 
+pub mod admin;
+pub mod audit;
+pub mod beacon;
+pub mod config;
+pub mod distribution;
+pub mod hub;
 pub mod lottery;
+pub mod merkle;
+pub mod metrics;
 pub mod routes;
 pub mod signatures;
+pub mod store;
+pub mod subgraph;
 pub mod tokens;
+pub mod voting_power;
 
 use std::env;
-extern crate dotenv;
-use dotenv::dotenv;
-use std::collections::HashMap;
 
 #[macro_use]
 extern crate lazy_static;
@@ -19,82 +27,135 @@ extern crate lazy_static;
 pub const MYRIAD: u16 = 10_000;
 
 lazy_static! {
-    static ref SUBGRAPH_URLS: HashMap<&'static str, String> = {
-        dotenv().ok();
-        let mut map = HashMap::new();
-
-        map.insert(
-            "1",
-            env::var("MAINNET_SUBGRAPH_URL")
-                .expect("Please add MAINNET_SUBGRAPH_URL to your environment or .env file."),
-        );
-
-        map.insert(
-            "11155111",
-            env::var("SEPOLIA_SUBGRAPH_URL")
-                .expect("Please add SEPOLIA_SUBGRAPH_URL to your environment or .env file."),
-        );
-
-        map.insert(
-            "137",
-            env::var("POLYGON_SUBGRAPH_URL")
-                .expect("Please add POLYGON_SUBGRAPH_URL to your environment or .env file."),
-        );
-
-        map.insert(
-            "8453",
-            env::var("BASE_SUBGRAPH_URL")
-                .expect("Please add BASE_SUBGRAPH_URL to your environment or .env file."),
-        );
-
-        map
-    };
     static ref BEACONCHAIN_API_KEY: String = env::var("BEACONCHAIN_API_KEY")
         .expect("Please add BEACONCHAIN_API_KEY to your environment or .env file");
-    static ref BOOST_NAME: String =
-        env::var("BOOST_NAME").expect("Please add BOOST_NAME to your environment or .env file");
-    static ref BOOST_VERSION: String = env::var("BOOST_VERSION")
-        .expect("Please add BOOST_VERSION to your environment or .env file");
-    static ref VERIFYING_CONTRACT: String = env::var("VERIFYING_CONTRACT")
-        .expect("Please add VERIFYING_CONTRACT to your environment or .env file");
     static ref EPOCH_URL: String =
         env::var("EPOCH_URL").expect("Please add EPOCH_URL to your environment or .env file");
     static ref SLOT_URL: String =
         env::var("SLOT_URL").expect("Please add SLOT_URL to your environment or .env file");
-    static ref DISABLED_TOKENS: HashSet<(Address, &'static str)> =
-        tokens::create_disabled_token_list();
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ServerError {
-    ErrorString(String),
-    ProposalStillInProgress,
+    NotFound(String),            // The proposal or boost does not exist
+    InvalidInput(String),        // The request is malformed
+    ProposalNotFinal,            // The proposal has not ended yet, or its scores are not final
+    OutsideClaimWindow(String),  // The boost can not be claimed at this time
+    AlreadyClaimed(String),      // The voter already claimed their reward
+    InsufficientPool(String),    // The boost does not hold enough tokens to pay the reward
+    UpstreamUnavailable(String), // The hub, the subgraph or the beacon node failed to answer
+    IneligibleVoter(String),     // The voter is not eligible to the boost
+    BoostMisconfigured(String),  // The boost can not be handled by the guard
+    Unauthorized(String),        // The admin token is missing or wrong
+    Internal(String),            // The guard is broken
 }
 
-impl<T: std::string::ToString + Sized> From<T> for ServerError {
-    fn from(err: T) -> Self {
-        ServerError::ErrorString(err.to_string())
+impl ServerError {
+    /// Machine-readable error code, returned in the response body.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::NotFound(_) => "not_found",
+            ServerError::InvalidInput(_) => "invalid_input",
+            ServerError::ProposalNotFinal => "proposal_not_final",
+            ServerError::OutsideClaimWindow(_) => "outside_claim_window",
+            ServerError::AlreadyClaimed(_) => "already_claimed",
+            ServerError::InsufficientPool(_) => "insufficient_pool",
+            ServerError::UpstreamUnavailable(_) => "upstream_unavailable",
+            ServerError::IneligibleVoter(_) => "ineligible_voter",
+            ServerError::BoostMisconfigured(_) => "boost_misconfigured",
+            ServerError::Unauthorized(_) => "unauthorized",
+            ServerError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ServerError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServerError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ServerError::ProposalNotFinal => StatusCode::CONFLICT,
+            ServerError::OutsideClaimWindow(_) => StatusCode::CONFLICT,
+            ServerError::AlreadyClaimed(_) => StatusCode::CONFLICT,
+            ServerError::InsufficientPool(_) => StatusCode::CONFLICT,
+            ServerError::UpstreamUnavailable(_) => StatusCode::BAD_GATEWAY,
+            ServerError::IneligibleVoter(_) => StatusCode::FORBIDDEN,
+            ServerError::BoostMisconfigured(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ServerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ServerError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 }
 
-impl IntoResponse for ServerError {
-    fn into_response(self) -> Response {
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ServerError::ErrorString(body) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
-            }
-            ServerError::ProposalStillInProgress => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Proposal has not ended yet",
-            )
-                .into_response(),
+            ServerError::NotFound(msg)
+            | ServerError::InvalidInput(msg)
+            | ServerError::OutsideClaimWindow(msg)
+            | ServerError::AlreadyClaimed(msg)
+            | ServerError::InsufficientPool(msg)
+            | ServerError::UpstreamUnavailable(msg)
+            | ServerError::IneligibleVoter(msg)
+            | ServerError::BoostMisconfigured(msg)
+            | ServerError::Unauthorized(msg)
+            | ServerError::Internal(msg) => f.write_str(msg),
+            ServerError::ProposalNotFinal => f.write_str("Proposal has not ended yet"),
         }
     }
 }
 
+impl From<mysql_async::Error> for ServerError {
+    fn from(err: mysql_async::Error) -> Self {
+        ServerError::UpstreamUnavailable(format!("hub: {}", err))
+    }
+}
+
+impl From<rusqlite::Error> for ServerError {
+    fn from(err: rusqlite::Error) -> Self {
+        ServerError::Internal(format!("store: {}", err))
+    }
+}
+
+impl From<reqwest::Error> for ServerError {
+    fn from(err: reqwest::Error) -> Self {
+        ServerError::UpstreamUnavailable(err.to_string())
+    }
+}
+
+// Requests are parsed with `parse_request`: any other (de)serialization failure is the guard's
+impl From<serde_json::Error> for ServerError {
+    fn from(err: serde_json::Error) -> Self {
+        ServerError::Internal(err.to_string())
+    }
+}
+
+/// Parses the JSON body of a request. A body that does not fit the parameters is the client's
+/// error.
+pub fn parse_request<T: serde::de::DeserializeOwned>(
+    body: serde_json::Value,
+) -> Result<T, ServerError> {
+    serde_json::from_value(body).map_err(|err| ServerError::InvalidInput(err.to_string()))
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({
+            "code": self.code(),
+            "message": self.to_string(),
+        }));
+        (self.status(), body).into_response()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct State {
-    pub client: reqwest::Client,
-    pub pool: mysql_async::Pool,
+    pub hub: Arc<dyn hub::HubStore>,
+    pub registry: Arc<dyn subgraph::BoostRegistry>,
+    pub beacon: Arc<dyn beacon::RandomnessBeacon>,
+    pub store: Arc<store::Store>,
+    // Append-only log of the signed vouchers
+    pub audit: Arc<audit::AuditLog>,
+    // Token of the admin endpoints, which are disabled if it is not set
+    pub admin_token: Option<String>,
+    pub config: Arc<config::Config>,
     pub wallet: ethers::signers::LocalWallet,
 }
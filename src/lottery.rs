@@ -1,87 +1,114 @@
-use crate::routes::{BoostInfo, ProposalInfo, Vote};
-use crate::{ServerError, BEACONCHAIN_API_KEY};
-use crate::{EPOCH_URL, MYRIAD, SLOT_URL};
+use crate::beacon::RandomnessBeacon;
+use crate::distribution::{allocate, Allocation, Weighting};
+use crate::hub::HubStore;
+use crate::routes::{BoostInfo, BoostKey, ProposalInfo, Vote};
+use crate::store::{Artifact, Store};
+use crate::voting_power::{mul_div, VotingPower};
+use crate::ServerError;
+use crate::MYRIAD;
 use cached::proc_macro::cached;
 use cached::TimedSizedCache;
 use durations::WEEK;
-use ethers::types::{Address, U256};
-use mysql_async::prelude::Queryable;
+use ethers::types::{Address, H256};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
-use serde_json::Value;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::HashSet;
+use std::sync::Arc;
 
-const FIRST_MERGED_SLOT: u64 = 4700013;
-const FIRST_MERGED_SLOT_TIMESTAMP: u64 = 1663224179;
-
-// LRU cache that uses `boost_id` and `chain_id` as keys
+// LRU cache keyed by boost
 #[cached(
     result = true,
     sync_writes = true,
-    type = "TimedSizedCache<String, HashMap<Address, U256>>",
+    type = "TimedSizedCache<BoostKey, Arc<Allocation>>",
     create = "{ TimedSizedCache::with_size_and_lifespan(500, 3 * WEEK.as_secs())}",
-    convert = r#"{ format!("{}{}", boost_info.id, boost_info.chain_id) }"#
+    convert = r#"{ boost_info.cache_key() }"#
 )]
 pub async fn cached_lottery_winners(
-    pool: &mysql_async::Pool,
+    hub: &dyn HubStore,
+    beacon: &dyn RandomnessBeacon,
+    store: &Store,
+    boost_info: &BoostInfo,
+    proposal_info: &ProposalInfo,
+    num_winners: u32,
+    limit: Option<u16>,
+) -> Result<Arc<Allocation>, ServerError> {
+    // The lottery is drawn once: the outcome is kept forever
+    let key = boost_info.cache_key();
+    if let Some(winners) = store.get(Artifact::LotteryWinners, &key)? {
+        return Ok(Arc::new(winners));
+    }
+
+    let (winners, seed) =
+        draw_lottery(hub, beacon, boost_info, proposal_info, num_winners, limit).await?;
+    // Kept for the audit of the vouchers
+    if let Some(seed) = seed {
+        store.put(Artifact::LotterySeed, &key, &H256::from(seed))?;
+    }
+    store.put(Artifact::LotteryWinners, &key, &winners)?;
+    Ok(Arc::new(winners))
+}
+
+// Draws the winners, and returns their rewards along with the seed if a draw was needed
+async fn draw_lottery(
+    hub: &dyn HubStore,
+    beacon: &dyn RandomnessBeacon,
     boost_info: &BoostInfo,
     proposal_info: &ProposalInfo,
     num_winners: u32,
     limit: Option<u16>,
-) -> Result<HashMap<Address, U256>, ServerError> {
+) -> Result<(Allocation, Option<[u8; 32]>), ServerError> {
     let bribed_choice = proposal_info.get_bribed_choice(&boost_info.params.eligibility)?;
 
-    let choice_constraint = if let Some(choice) = bribed_choice {
-        format!("AND choice = {}", choice)
-    } else {
-        "".to_string()
-    };
-
-    let query = format!(
-        "SELECT voter, vp, choice
-        FROM votes
-        WHERE proposal = '{}'
-        {}
-        ORDER BY vp DESC;",
-        proposal_info.id, choice_constraint
-    );
-
-    let mut conn = pool.get_conn().await?;
-    let result: Vec<(String, f64, String)> = conn.query(query).await?;
-    conn.disconnect().await?;
-
-    if result.is_empty() {
+    let votes = hub.votes(&proposal_info.id, bribed_choice).await?;
+
+    if votes.is_empty() {
         tracing::warn!("No votes found for proposal {}", proposal_info.id);
-        return Ok(HashMap::new());
+        return Ok((Allocation::default(), None));
     }
 
-    let mut votes = result
-        .into_iter()
-        .map(|(voter, vp, _)| {
-            Ok(Vote {
-                voter: Address::from_str(&voter)?,
-                voting_power: vp,
-            })
-        })
-        .collect::<Result<Vec<Vote>, ServerError>>()?;
-
     // If there are not enough voters, then every voter is eligible to the same reward
     if votes.len() <= num_winners as usize {
         tracing::warn!("Not enough voters to enforce the limit");
-        let prize = boost_info.pool_size / votes.len() as u32;
-        return Ok(votes.into_iter().map(|v| (v.voter, prize)).collect());
+        return Ok((
+            allocate(&votes, boost_info.pool_size, Weighting::Even, None)?,
+            None,
+        ));
     }
 
-    if let Some(limit) = limit {
-        adjust_vote_weights(&mut votes, boost_info.decimals, proposal_info.score, limit)?;
+    let votes = lottery_weights(votes, proposal_info, num_winners, limit)?;
+
+    let seed = get_randao_reveal(beacon, proposal_info.end).await?;
+    let winners = draw_winners(&votes, seed, num_winners);
+
+    // The winners share the pool evenly
+    let votes: Vec<Vote> = votes
+        .into_iter()
+        .filter(|v| winners.contains(&v.voter))
+        .collect();
+    Ok((
+        allocate(&votes, boost_info.pool_size, Weighting::Even, None)?,
+        Some(seed),
+    ))
+}
+
+/// Returns the votes with the voting power each voter is drawn with, once the limit is enforced.
+pub fn lottery_weights(
+    mut votes: Vec<Vote>,
+    proposal_info: &ProposalInfo,
+    num_winners: u32,
+    limit: Option<u16>,
+) -> Result<Vec<Vote>, ServerError> {
+    // Every voter wins when there are not enough of them: the limit is not enforced
+    if votes.len() <= num_winners as usize {
+        return Ok(votes);
     }
 
-    let prize = boost_info.pool_size / num_winners;
-    let seed = get_randao_reveal(proposal_info.end).await?;
+    if let Some(limit) = limit {
+        adjust_vote_weights(&mut votes, proposal_info.score, limit)?;
+    }
 
-    Ok(draw_winners(votes, seed, num_winners, prize))
+    Ok(votes)
 }
 
 // Adjust the voting power of the voters to respect the limit.
@@ -92,8 +119,7 @@ pub async fn cached_lottery_winners(
 // The array of `votes` is assumed to be sorted by voting power.
 fn adjust_vote_weights(
     votes: &mut [Vote],
-    decimals: u8,
-    score: f64,
+    score: VotingPower,
     limit: u16,
 ) -> Result<(), ServerError> {
     if limit == 0 {
@@ -112,18 +138,16 @@ fn adjust_vote_weights(
         .windows(2)
         .any(|w| w[0].voting_power < w[1].voting_power)
     {
-        return Err(ServerError::ErrorString("votes are not sorted".to_string()));
+        return Err(ServerError::Internal("votes are not sorted".to_string()));
     }
 
-    if votes.len() < (MYRIAD as f64 / limit as f64).ceil() as usize {
+    if votes.len() < (MYRIAD as usize).div_ceil(limit as usize) {
         tracing::warn!("not enough voters to enforce the limit");
         return Ok(());
     }
 
-    let pow = 10_f64.powi(decimals as i32);
-
     // The "voting power" remaining. At each iteration, we will subtract the voting power of the voter.
-    let mut remaining_score = U256::from((score * pow) as u128);
+    let mut remaining_score = score.raw();
 
     // The "effective voting power" remaining. At each iteration, we will subtract the effective voting power of the voter.
     let mut effective_remaining_score = remaining_score;
@@ -132,53 +156,38 @@ fn adjust_vote_weights(
     let vp_limit = remaining_score * limit / MYRIAD;
 
     votes.iter_mut().for_each(|v| {
-        let vp = U256::from((v.voting_power * pow) as u128);
+        let vp = v.voting_power.raw();
         // If the user reaches the limit, assign the limit, else assign the correct ratio.
-        let effective_voting_power =
-            std::cmp::min(vp_limit, effective_remaining_score * vp / remaining_score);
-
-        // Subtract the voting power.
-        // To prevent an arithmetic underflow, we check if the voting power is greater than the remaining score.
-        // If it is, this means there's a small rounding error on the last voter.
-        // This happens because the EVM, assembly-script and rust don't handle floats the same way.
-        if vp > remaining_score {
-            remaining_score = U256::from(0);
-        } else {
-            remaining_score -= vp;
-        }
+        let effective_voting_power = std::cmp::min(
+            vp_limit,
+            mul_div(effective_remaining_score, vp, remaining_score),
+        );
 
-        // Subtract the effective voting power.
-        // Same logic regarding underflows as the comment above.
-        if effective_voting_power > effective_remaining_score {
-            effective_remaining_score = U256::from(0);
-        } else {
-            effective_remaining_score -= effective_voting_power;
-        }
+        // Subtract the voting power. The total score of the hub may be a few units lower than the
+        // exact sum of the votes: saturate instead of underflowing on the last voter.
+        remaining_score = remaining_score.saturating_sub(vp);
+        effective_remaining_score =
+            effective_remaining_score.saturating_sub(effective_voting_power);
 
         // Update the voter's voting power
-        v.voting_power = effective_voting_power.as_u128() as f64 / pow;
+        v.voting_power = VotingPower::from_raw(effective_voting_power);
     });
 
     Ok(())
 }
 
-fn draw_winners(
-    votes: Vec<Vote>,
-    seed: [u8; 32],
-    num_winners: u32,
-    prize: U256,
-) -> HashMap<Address, U256> {
+fn draw_winners(votes: &[Vote], seed: [u8; 32], num_winners: u32) -> HashSet<Address> {
     let mut rng = ChaCha20Rng::from_seed(seed);
-    let mut winners = HashMap::with_capacity(num_winners as usize);
-
-    let mut set = std::collections::HashSet::new();
+    let mut winners = HashSet::with_capacity(num_winners as usize);
 
-    // Construct the cumulative weights (e.g; [1, 2, 3, 4] -> [1, 3, 6, 10])
+    // Construct the cumulative weights (e.g; [1, 2, 3, 4] -> [1, 3, 6, 10]).
+    // They stay floats: a lottery drawn before the voting power was fixed-point must keep its
+    // winners, whose vouchers may already be signed.
     let mut cumulative_weights = Vec::with_capacity(votes.len());
-    let mut curr = votes[0].voting_power;
+    let mut curr = votes[0].voting_power.to_f64();
     cumulative_weights.push(curr);
     for v in votes.iter().skip(1) {
-        curr += v.voting_power;
+        curr += v.voting_power.to_f64();
         cumulative_weights.push(curr);
     }
 
@@ -195,7 +204,7 @@ fn draw_winners(
             let winner = votes.get(idx).unwrap().voter;
 
             // If the winner has been selected before, draw again.
-            if set.contains(&winner) {
+            if winners.contains(&winner) {
                 continue;
             } else {
                 break winner;
@@ -203,75 +212,19 @@ fn draw_winners(
         };
 
         // Add winner to the set
-        set.insert(winner);
-        // Add winner to the winners map
-        winners.insert(winner, prize);
+        winners.insert(winner);
     }
     winners
 }
 
-// This function tries to map a timestamp to a randao reveal.
-// A randao reveal is a source of randomness provided by the beacon chain. Each epoch has a randao reveal.
-// This function tries to find the epoch that contains the timestamp and returns the randao reveal of that epoch.
-// It does so by following these steps:
-// Step 1: Find the closest slot corresponding to the timestamp (round up to nearest multiple of 12, because slots are 12 seconds long).
-// Step 2: Query the SLOT_URL to get the corresponding slot, and extract its epoch.
-// Step 3: Query the EPOCH_URL to get the corresponding epoch.
-// Step 4: Ensure the epoch is finalized, and return its randao reveal.
-async fn randao_from_timestamp(timestamp: u64) -> Result<String, ServerError> {
-    let client = reqwest::Client::new();
-
-    // Step 1
-    let elapsed = timestamp - FIRST_MERGED_SLOT_TIMESTAMP;
-    let rounded_elapsed = elapsed + (12 - elapsed % 12);
-    let elapsed_slots = rounded_elapsed / 12;
-    let nearest_slot = FIRST_MERGED_SLOT + elapsed_slots;
-
-    // Step 2
-    let slot_url = format!(
-        "{}{}?apikey={}",
-        SLOT_URL.as_str(),
-        nearest_slot,
-        BEACONCHAIN_API_KEY.as_str()
-    );
-    let slot: Value = client.get(&slot_url).send().await?.json().await?;
-    tracing::info!(?slot);
-    let epoch = slot["data"]["epoch"]
-        .as_u64()
-        .ok_or("failed to parse epoch")?;
-    tracing::info!(?epoch);
-
-    // Step 3
-    let epoch_url = format!(
-        "{}{}?apikey={}",
-        EPOCH_URL.as_str(),
-        epoch,
-        BEACONCHAIN_API_KEY.as_str()
-    );
-    let epoch_details: Value = client.get(&epoch_url).send().await?.json().await?;
-
-    // Step 4
-    let finalized = epoch_details["data"]["finalized"]
-        .as_bool()
-        .ok_or("finalized is not a boolean")?;
-    if !finalized {
-        return Err("epoch is not finalized".into());
-    }
-
-    let randao_reveal = slot["data"]["randaoreveal"]
-        .as_str()
-        .ok_or("randao_reveal is not a string")?
-        .to_string();
-
-    tracing::info!(?randao_reveal);
-    Ok(randao_reveal)
-}
-
 // Create a 32bytes seed with the sha256 hash of the randao reveal corresponding to
 // the next nearest epoch of the given timestamp.
-async fn get_randao_reveal(timestamp: u64) -> Result<[u8; 32], ServerError> {
+async fn get_randao_reveal(
+    beacon: &dyn RandomnessBeacon,
+    timestamp: u64,
+) -> Result<[u8; 32], ServerError> {
     // Step 1: Get the randao reveal from the chain
-    let randao = randao_from_timestamp(timestamp).await?;
+    let randao = beacon.randao_reveal(timestamp).await?;
     let bytes = hex::decode(&randao[2..]).unwrap();
 
     // Step 2: Hash the byte array
@@ -288,7 +241,8 @@ async fn get_randao_reveal(timestamp: u64) -> Result<[u8; 32], ServerError> {
 mod test_draw_winners {
     use super::draw_winners;
     use super::Vote;
-    use super::U256;
+    use super::VotingPower;
+    use ethers::types::Address;
     use rand::Rng;
     use rand::SeedableRng;
     use rand_chacha::ChaCha8Rng;
@@ -296,23 +250,21 @@ mod test_draw_winners {
     #[test]
     fn test_randomness() {
         let vote1 = Vote {
-            voting_power: 99.0,
+            voting_power: VotingPower::from(99),
             ..Default::default()
         };
         let vote2 = Vote {
-            voting_power: 1.0,
+            voting_power: VotingPower::from(1),
             ..Default::default()
         };
         let votes = vec![vote1.clone(), vote2.clone()];
-        let prize = U256::from(10);
-
         let mut rng = ChaCha8Rng::from_entropy();
         let mut num = 0;
 
         // Draw 10000 times, expect voter 2 to get picked about 100 times.
         for _ in 0..10000 {
-            let winners = draw_winners(votes.clone(), rng.gen(), 1, prize);
-            if winners.get(&vote2.voter).is_some() {
+            let winners = draw_winners(&votes, rng.gen(), 1);
+            if winners.contains(&vote2.voter) {
                 num += 1;
             }
         }
@@ -322,26 +274,52 @@ mod test_draw_winners {
         assert!(num <= 130);
     }
 
+    // Seeds drawn before the voting power was fixed-point keep their winners: the expected sets
+    // were drawn with the float implementation
+    #[test]
+    fn test_known_draws() {
+        let votes: Vec<Vote> = [
+            "12.5", "11", "10.75", "10", "9.5", "9", "8.25", "8", "7.5", "7",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, vp)| Vote {
+            voter: Address::from_low_u64_be(i as u64),
+            voting_power: vp.parse().unwrap(),
+        })
+        .collect();
+        let winners = |seed| {
+            let mut winners: Vec<u64> = draw_winners(&votes, seed, 3)
+                .into_iter()
+                .map(|winner| winner.to_low_u64_be())
+                .collect();
+            winners.sort();
+            winners
+        };
+
+        assert_eq!(winners([7; 32]), vec![2, 3, 8]);
+        assert_eq!(winners([42; 32]), vec![0, 4, 7]);
+    }
+
     #[test]
     fn select_two() {
         let vote1 = Vote {
-            voting_power: 98.0,
+            voting_power: VotingPower::from(98),
             ..Default::default()
         };
         let vote2 = Vote {
-            voting_power: 1.0,
+            voting_power: VotingPower::from(1),
             ..Default::default()
         };
         let vote3 = Vote {
-            voting_power: 1.0,
+            voting_power: VotingPower::from(1),
             ..Default::default()
         };
         let votes = vec![vote1.clone(), vote2.clone(), vote3.clone()];
-        let prize = U256::from(10);
 
         let mut rng = ChaCha8Rng::from_entropy();
 
-        let winners = draw_winners(votes, rng.gen(), 2, prize);
+        let winners = draw_winners(&votes, rng.gen(), 2);
         assert_eq!(winners.len(), 2);
     }
 
@@ -351,16 +329,15 @@ mod test_draw_winners {
         let votes = (0..1000000)
             .enumerate()
             .map(|(i, _)| Vote {
-                voting_power: i as f64,
+                voting_power: VotingPower::from(i as u64),
                 ..Default::default()
             })
-            .collect();
-        let prize = U256::from(10);
+            .collect::<Vec<_>>();
 
         let mut rng = ChaCha8Rng::from_entropy();
 
         let start = std::time::Instant::now();
-        let _ = draw_winners(votes, rng.gen(), 1000, prize);
+        let _ = draw_winners(&votes, rng.gen(), 1000);
         let finish = std::time::Instant::now();
         println!("Time: {:?}", finish - start);
     }
@@ -370,210 +347,218 @@ mod test_draw_winners {
 mod test_adjust_vote_weights {
     use super::adjust_vote_weights;
     use super::Vote;
+    use super::VotingPower;
 
     #[test]
     fn test_adjust_vote_weights_half() {
         let mut votes = vec![
             Vote {
-                voting_power: 900.0,
+                voting_power: VotingPower::from(900),
                 ..Default::default()
             },
             Vote {
-                voting_power: 100.0,
+                voting_power: VotingPower::from(100),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 5000; // 50 %
 
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 500.0);
-        assert_eq!(votes[1].voting_power, 500.0);
+        assert_eq!(votes[0].voting_power, VotingPower::from(500));
+        assert_eq!(votes[1].voting_power, VotingPower::from(500));
     }
 
     #[test]
     fn test_adjust_no_op() {
         let mut votes = vec![
             Vote {
-                voting_power: 900.0,
+                voting_power: VotingPower::from(900),
                 ..Default::default()
             },
             Vote {
-                voting_power: 100.0,
+                voting_power: VotingPower::from(100),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 100; // 1 %
 
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 900.0);
-        assert_eq!(votes[1].voting_power, 100.0);
+        assert_eq!(votes[0].voting_power, VotingPower::from(900));
+        assert_eq!(votes[1].voting_power, VotingPower::from(100));
     }
 
     #[test]
     fn test_adjust_limit_zero() {
         let mut votes = vec![
             Vote {
-                voting_power: 900.0,
+                voting_power: VotingPower::from(900),
                 ..Default::default()
             },
             Vote {
-                voting_power: 100.0,
+                voting_power: VotingPower::from(100),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 0; // 0 %
 
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 900.0);
-        assert_eq!(votes[1].voting_power, 100.0);
+        assert_eq!(votes[0].voting_power, VotingPower::from(900));
+        assert_eq!(votes[1].voting_power, VotingPower::from(100));
     }
 
     #[test]
     fn test_adjust_limit_fourty() {
         let mut votes = vec![
             Vote {
-                voting_power: 10.0,
+                voting_power: VotingPower::from(10),
                 ..Default::default()
             },
             Vote {
-                voting_power: 10.0,
+                voting_power: VotingPower::from(10),
                 ..Default::default()
             },
             Vote {
-                voting_power: 1.0,
+                voting_power: VotingPower::from(1),
                 ..Default::default()
             },
             Vote {
-                voting_power: 1.0,
+                voting_power: VotingPower::from(1),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 4000; // 40 %
 
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 8.8);
-        assert_eq!(votes[1].voting_power, 8.8);
-        assert_eq!(votes[2].voting_power, 2.2);
-        assert_eq!(votes[3].voting_power, 2.2);
+        assert_eq!(votes[0].voting_power, "8.8".parse().unwrap());
+        assert_eq!(votes[1].voting_power, "8.8".parse().unwrap());
+        assert_eq!(votes[2].voting_power, "2.2".parse().unwrap());
+        assert_eq!(votes[3].voting_power, "2.2".parse().unwrap());
     }
 
     #[test]
     fn test_adjust_limit_no_op_rounded() {
         let mut votes = vec![
             Vote {
-                voting_power: 900.0,
+                voting_power: VotingPower::from(900),
                 ..Default::default()
             },
             Vote {
-                voting_power: 50.0,
+                voting_power: VotingPower::from(50),
                 ..Default::default()
             },
             Vote {
-                voting_power: 50.0,
+                voting_power: VotingPower::from(50),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 3000; // 30 %
 
         // Would need 4 voters but we only have three so no-op
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 900.0);
-        assert_eq!(votes[1].voting_power, 50.0);
-        assert_eq!(votes[2].voting_power, 50.0);
+        assert_eq!(votes[0].voting_power, VotingPower::from(900));
+        assert_eq!(votes[1].voting_power, VotingPower::from(50));
+        assert_eq!(votes[2].voting_power, VotingPower::from(50));
     }
 
     #[test]
     fn test_adjust_limit_rounded() {
         let mut votes = vec![
             Vote {
-                voting_power: 800.0,
+                voting_power: VotingPower::from(800),
                 ..Default::default()
             },
             Vote {
-                voting_power: 100.0,
+                voting_power: VotingPower::from(100),
                 ..Default::default()
             },
             Vote {
-                voting_power: 50.0,
+                voting_power: VotingPower::from(50),
                 ..Default::default()
             },
             Vote {
-                voting_power: 50.0,
+                voting_power: VotingPower::from(50),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 3000; // 30 %
 
         // We indeed have 4 voters, votes should get adjusted
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
 
-        assert_eq!(votes[0].voting_power, 300.0);
-        assert_eq!(votes[1].voting_power, 300.0);
-        assert_eq!(votes[2].voting_power, 200.0);
-        assert_eq!(votes[2].voting_power, 200.0);
+        assert_eq!(votes[0].voting_power, VotingPower::from(300));
+        assert_eq!(votes[1].voting_power, VotingPower::from(300));
+        assert_eq!(votes[2].voting_power, VotingPower::from(200));
+        assert_eq!(votes[2].voting_power, VotingPower::from(200));
     }
 
     #[test]
     fn test_adjust_vote_weights() {
         let mut votes = vec![
             Vote {
-                voting_power: 458.0,
+                voting_power: VotingPower::from(458),
                 ..Default::default()
             },
             Vote {
-                voting_power: 200.0,
+                voting_power: VotingPower::from(200),
                 ..Default::default()
             },
             Vote {
-                voting_power: 180.0,
+                voting_power: VotingPower::from(180),
                 ..Default::default()
             },
             Vote {
-                voting_power: 150.0,
+                voting_power: VotingPower::from(150),
                 ..Default::default()
             },
             Vote {
-                voting_power: 5.0,
+                voting_power: VotingPower::from(5),
                 ..Default::default()
             },
             Vote {
-                voting_power: 4.0,
+                voting_power: VotingPower::from(4),
                 ..Default::default()
             },
             Vote {
-                voting_power: 3.0,
+                voting_power: VotingPower::from(3),
                 ..Default::default()
             },
         ];
-        let decimals = 18;
-        let score = votes.iter().map(|v| v.voting_power).sum::<f64>();
+        let score = votes.iter().map(|v| v.voting_power).sum::<VotingPower>();
         let limit = 2000; // 20 %
 
-        adjust_vote_weights(&mut votes, decimals, score, limit).unwrap();
-
-        assert_eq!(votes[0].voting_power, 200.0);
-        assert_eq!(votes[1].voting_power, 200.0);
-        assert_eq!(votes[2].voting_power, 200.0);
-        assert_eq!(votes[3].voting_power, 200.0);
-        assert_eq!(votes[4].voting_power, 83.33333333333333);
-        assert_eq!(votes[5].voting_power, 66.66666666666666);
-        assert_eq!(votes[6].voting_power, 50.0);
+        adjust_vote_weights(&mut votes, score, limit).unwrap();
+
+        assert_eq!(votes[0].voting_power, VotingPower::from(200));
+        assert_eq!(votes[1].voting_power, VotingPower::from(200));
+        assert_eq!(votes[2].voting_power, VotingPower::from(200));
+        assert_eq!(votes[3].voting_power, VotingPower::from(200));
+        assert_eq!(
+            votes[4].voting_power,
+            "83.333333333333333333".parse().unwrap()
+        );
+        assert_eq!(
+            votes[5].voting_power,
+            "66.666666666666666666".parse().unwrap()
+        );
+        // The last voter gets what the rounding left over: nothing is lost
+        assert_eq!(
+            votes[6].voting_power,
+            "50.000000000000000001".parse().unwrap()
+        );
+        assert_eq!(
+            votes.iter().map(|v| v.voting_power).sum::<VotingPower>(),
+            score
+        );
     }
 }
@@ -1,19 +1,52 @@
+use axum::middleware;
 use axum::routing::{get, post};
 use axum::{Extension, Router};
-use boost_guard::routes::{handle_create_vouchers, handle_get_rewards, handle_health, handle_root};
+use boost_guard::admin::{handle_cache_stats, handle_evict_cache, handle_inspect_cache};
+use boost_guard::audit::{audit_to_csv, handle_audit_vouchers, AuditFilter, AuditLog};
+use boost_guard::beacon::Beaconchain;
+use boost_guard::config::Config;
+use boost_guard::hub::MysqlHub;
+use boost_guard::metrics::{handle_metrics, track_requests};
+use boost_guard::routes::{
+    distribution_to_csv, get_boost_distribution, handle_boost_distribution, handle_claim_status,
+    handle_claimable_rewards, handle_create_vouchers, handle_create_vouchers_batch,
+    handle_explain_rewards, handle_get_rewards, handle_health, handle_merkle_proof,
+    handle_merkle_root, handle_root, DistributionFormat,
+};
+use boost_guard::store::Store;
+use boost_guard::subgraph::SubgraphRegistry;
 use mysql_async::Pool;
 use std::env;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 extern crate dotenv;
 
 use dotenv::dotenv;
 
+#[cfg(test)]
+mod test_harness;
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("distribution") {
+        // Traces go to stderr so that the output can be piped
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+        return export_distribution(&args[2..]).await;
+    }
+    if args.get(1).map(String::as_str) == Some("audit") {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+        return export_audit_log(&args[2..]);
+    }
+
     // construct a subscriber that prints formatted traces to stdout
     let subscriber = tracing_subscriber::FmtSubscriber::new();
     // use that subscriber to process traces emitted after this point
@@ -26,47 +59,144 @@ async fn main() {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = TcpListener::bind(addr).await.unwrap();
 
-    axum::serve(listener, app()).await.unwrap();
+    axum::serve(listener, app(state_from_env())).await.unwrap();
 }
 
-fn app() -> Router {
+fn state_from_env() -> boost_guard::State {
     dotenv().ok();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let pool = Pool::new(database_url.as_str());
 
+    let config = Config::load().unwrap_or_else(|err| panic!("{}", err));
+
     let client = reqwest::Client::new();
 
     let private_key = env::var("PRIVATE_KEY").expect("PRIVATE_KEY must be set");
     let wallet = ethers::signers::LocalWallet::from_str(&private_key)
         .expect("failed to create a local wallet");
-    let state = boost_guard::State {
-        client,
-        pool,
+    boost_guard::State {
+        hub: Arc::new(MysqlHub::new(pool)),
+        registry: Arc::new(SubgraphRegistry::from_config(client.clone(), &config)),
+        beacon: Arc::new(Beaconchain::from_env(client)),
+        store: Arc::new(Store::from_env().unwrap_or_else(|err| panic!("{}", err))),
+        audit: Arc::new(AuditLog::from_env().unwrap_or_else(|err| panic!("{}", err))),
+        admin_token: env::var("ADMIN_TOKEN").ok(),
+        config: Arc::new(config),
         wallet,
+    }
+}
+
+// `boost-guard distribution <proposal_id> <boost_id> <chain_id> [json|csv]`: prints the payout
+// table of a boost.
+async fn export_distribution(args: &[String]) {
+    let usage = "usage: boost-guard distribution <proposal_id> <boost_id> <chain_id> [json|csv]";
+    let (proposal_id, boost_id, chain_id) = match args {
+        [proposal_id, boost_id, chain_id, ..] if args.len() <= 4 => {
+            (proposal_id, boost_id, chain_id)
+        }
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
     };
+    let format = match args.get(3).map(String::as_str) {
+        None | Some("json") => DistributionFormat::Json,
+        Some("csv") => DistributionFormat::Csv,
+        Some(_) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+
+    let state = state_from_env();
+    match get_boost_distribution(&state, proposal_id, boost_id, chain_id).await {
+        Ok(distribution) => match format {
+            DistributionFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&distribution).unwrap())
+            }
+            DistributionFormat::Csv => print!("{}", distribution_to_csv(&distribution)),
+        },
+        Err(error) => {
+            eprintln!("{}: {}", error.code(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+// `boost-guard audit [json|csv]`: prints every voucher recorded in the audit log at
+// `AUDIT_LOG_PATH`, oldest first.
+fn export_audit_log(args: &[String]) {
+    let usage = "usage: boost-guard audit [json|csv]";
+    let format = match args {
+        [] => DistributionFormat::Json,
+        [format] if format == "json" => DistributionFormat::Json,
+        [format] if format == "csv" => DistributionFormat::Csv,
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    if env::var("AUDIT_LOG_PATH").is_err() {
+        eprintln!("AUDIT_LOG_PATH must be set");
+        std::process::exit(2);
+    }
 
+    match AuditLog::from_env().and_then(|log| log.entries(&AuditFilter::default())) {
+        Ok(entries) => match format {
+            DistributionFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&entries).unwrap())
+            }
+            DistributionFormat::Csv => print!("{}", audit_to_csv(&entries)),
+        },
+        Err(error) => {
+            eprintln!("{}: {}", error.code(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn app(state: boost_guard::State) -> Router {
     Router::new()
         .route("/create-vouchers", post(handle_create_vouchers))
+        .route("/create-vouchers-batch", post(handle_create_vouchers_batch))
         .route("/get-rewards", post(handle_get_rewards))
+        .route("/explain-rewards", post(handle_explain_rewards))
+        .route("/claim-status", post(handle_claim_status))
+        .route("/claimable-rewards", post(handle_claimable_rewards))
+        .route("/boost-distribution", post(handle_boost_distribution))
+        .route("/merkle-root", post(handle_merkle_root))
+        .route("/merkle-proof", post(handle_merkle_proof))
         .route(
             "/get-lottery-winners",
             post(boost_guard::routes::handle_get_lottery_winners),
         )
+        .route("/admin/caches", get(handle_cache_stats))
+        .route("/admin/caches/inspect", post(handle_inspect_cache))
+        .route("/admin/caches/evict", post(handle_evict_cache))
+        .route("/admin/vouchers", post(handle_audit_vouchers))
         .route("/health", get(handle_health))
+        .route("/metrics", get(handle_metrics))
         .route("/", get(handle_root))
+        .route_layer(middleware::from_fn(track_requests))
         .layer(Extension(state))
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_unwrap)]
 mod tests {
     use axum::body::Body;
     use axum::http;
+    use boost_guard::admin::{CacheEntry, CacheStats};
+    use boost_guard::audit::AuditEntry;
     use boost_guard::routes::{
-        CreateVouchersResponse, GetLotteryWinnerQueryParams, GetLotteryWinnersResponse,
-        GetRewardsResponse, GuardInfoResponse, QueryParams,
+        BatchVouchersResponse, ClaimStatusResponse, ClaimableResponse, CreateVouchersResponse,
+        DistributionEntry, ExplainRewardsResponse, GetLotteryWinnerQueryParams,
+        GetLotteryWinnersResponse, GetRewardsResponse, GuardInfoResponse, MerkleProofResponse,
+        MerkleRootResponse, QueryParams,
     };
     use http_body_util::BodyExt;
+    use serde_json::{json, Value};
     use tower::ServiceExt;
 
     const WINNER: &str = "0x3901D0fDe202aF1427216b79f5243f8A022d68cf";
@@ -76,11 +206,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_vouchers() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: PROPOSAL_ID.to_string(),
             voter_address: WINNER.to_string(),
-            boosts: vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())],
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
         };
 
         let response = app
@@ -107,16 +237,102 @@ mod tests {
             assert_eq!(result[0].reward, "10000000000000000");
             assert_eq!(result[0].chain_id, CHAIN_ID);
             assert_eq!(result[0].boost_id, BOOST_ID);
+            assert_eq!(result[0].domain.name, "boost");
+            assert_eq!(result[0].domain.version, "0.1.0");
+            assert_eq!(result[0].domain.chain_id, CHAIN_ID);
+            assert_eq!(
+                result[0].domain.verifying_contract,
+                "0x8E8913197114c911F13cfBfCBBD138C1DC74B964"
+                    .parse()
+                    .unwrap()
+            );
         }
     }
 
+    #[tokio::test]
+    async fn test_create_vouchers_batch() {
+        let app = crate::test_harness::app().await;
+        let query = json!({
+            "voter_address": WINNER,
+            "proposals": [
+                { "proposal_id": PROPOSAL_ID, "boosts": [[BOOST_ID, CHAIN_ID]] },
+                // The winner did not vote on the ranked choice proposal
+                {
+                    "proposal_id": "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb",
+                    "boosts": [["43", CHAIN_ID]],
+                },
+                { "proposal_id": "0x1234", "boosts": [[BOOST_ID, CHAIN_ID]] },
+            ],
+        });
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/create-vouchers-batch")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<BatchVouchersResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(result.len(), 3);
+
+        // Entries are in the order of the request, and errors do not fail the batch
+        assert_eq!(result[0].proposal_id, PROPOSAL_ID);
+        assert!(result[0].error.is_none());
+        assert_eq!(result[0].vouchers.len(), 1);
+        assert_eq!(result[0].vouchers[0].signature, "0x3099eca443b11fbcc85e0e5a772eb0276aceb2060d440edce2474b8bb5e28ce0727180bf08b88030bb0d5ed7592dd36b2c42622777cb485cfa47baae321772eb1c");
+
+        assert!(result[1].vouchers.is_empty());
+        assert_eq!(result[1].error.as_ref().unwrap().code, "ineligible_voter");
+
+        assert_eq!(result[2].proposal_id, "0x1234");
+        assert_eq!(result[2].error.as_ref().unwrap().code, "invalid_input");
+    }
+
+    #[tokio::test]
+    async fn test_explain_rewards_discovers_boosts() {
+        let app = crate::test_harness::app().await;
+        // No `boosts`: every boost of the proposal is considered. They take several pages of the
+        // subgraph, and the subgraph of the other chain is down.
+        let query = json!({
+            "proposal_id": "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb",
+            "voter_address": "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd",
+        });
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/explain-rewards")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<ExplainRewardsResponse> = serde_json::from_slice(&bytes).unwrap();
+        let boost_ids: Vec<&str> = result.iter().map(|r| r.boost_id.as_str()).collect();
+        assert_eq!(boost_ids, vec!["42", "43", "48", "50"]);
+        assert!(result.iter().all(|r| r.chain_id == CHAIN_ID));
+        assert_eq!(result[1].reward.as_deref(), Some("15000000000000000000"));
+    }
+
     #[tokio::test]
     async fn test_get_rewards() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: PROPOSAL_ID.to_string(),
             voter_address: WINNER.to_string(),
-            boosts: vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())],
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
         };
 
         let response = app
@@ -147,7 +363,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_lottery_winners() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = GetLotteryWinnerQueryParams {
             proposal_id: PROPOSAL_ID.to_string(),
             boost_id: BOOST_ID.to_string(),
@@ -179,9 +395,135 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_boost_distribution() {
+        let request = |format: &str| {
+            let query = json!({
+                "proposal_id": PROPOSAL_ID,
+                "boost_id": BOOST_ID,
+                "chain_id": CHAIN_ID,
+                "format": format,
+            });
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri("/boost-distribution")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                .unwrap()
+        };
+
+        let app = crate::test_harness::app().await;
+        let response = app.oneshot(request("json")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<DistributionEntry> = serde_json::from_slice(&bytes).unwrap();
+
+        // Every voter is listed, only the lottery winner gets the prize
+        assert_eq!(result.len(), 3);
+        for entry in &result {
+            assert_eq!(entry.weight, entry.voting_power);
+            if entry.voter == WINNER.parse().unwrap() {
+                assert_eq!(entry.reward, "10000000000000000");
+            } else {
+                assert_eq!(entry.reward, "0");
+            }
+        }
+
+        let app = crate::test_harness::app().await;
+        let response = app.oneshot(request("csv")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers()[http::header::CONTENT_TYPE], "text/csv");
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let csv = String::from_utf8(bytes.to_vec()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "voter,voting_power,weight,reward");
+        assert_eq!(
+            lines[1],
+            "0x3901d0fde202af1427216b79f5243f8a022d68cf,60,60,10000000000000000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merkle_distribution() {
+        let post = |uri: &str, body: Value| {
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri(uri)
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
+        // Weighted boost, split between two voters
+        let proposal_id = "0xe175412d46744bdb68e61c89492a5d3ebb55a487cf8fc4d35a0d671302babed3";
+        let boost_id = "49";
+
+        let app = crate::test_harness::app().await;
+        let response = app
+            .oneshot(post(
+                "/merkle-root",
+                json!({ "proposal_id": proposal_id, "boost_id": boost_id, "chain_id": CHAIN_ID }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let root: MerkleRootResponse = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(root.num_recipients, 2);
+        assert_eq!(root.total, "30000000000000000000");
+        assert_eq!(root.domain.version, "0.1.0");
+        assert!(root.signature.starts_with("0x"));
+
+        for voter in [
+            "0xc83A9e69012312513328992d454290be85e95101",
+            "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd",
+        ] {
+            let app = crate::test_harness::app().await;
+            let response = app
+                .oneshot(post(
+                    "/merkle-proof",
+                    json!({
+                        "proposal_id": proposal_id,
+                        "voter_address": voter,
+                        "boost_id": boost_id,
+                        "chain_id": CHAIN_ID,
+                    }),
+                ))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), http::StatusCode::OK);
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let proof: MerkleProofResponse = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(proof.root, root.root);
+            assert_eq!(proof.reward, "15000000000000000000");
+            assert!(boost_guard::merkle::verify(
+                root.root,
+                voter.parse().unwrap(),
+                ethers::types::U256::from_dec_str(&proof.reward).unwrap(),
+                &proof.proof
+            ));
+        }
+
+        // Lottery losers are not part of the tree
+        let app = crate::test_harness::app().await;
+        let response = app
+            .oneshot(post(
+                "/merkle-proof",
+                json!({
+                    "proposal_id": PROPOSAL_ID,
+                    "voter_address": "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd",
+                    "boost_id": BOOST_ID,
+                    "chain_id": CHAIN_ID,
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+    }
+
     #[tokio::test]
     async fn test_health_check() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let response = app
             .oneshot(
                 http::Request::builder()
@@ -197,7 +539,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_root() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let response = app
             .oneshot(
                 http::Request::builder()
@@ -220,15 +562,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rewards_ranked_choice() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb"
                 .to_string(),
             voter_address: "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd".to_string(),
-            boosts: vec![
+            boosts: Some(vec![
                 ("42".to_string(), "11155111".to_string()),
                 ("43".to_string(), "11155111".to_string()),
-            ],
+            ]),
         };
 
         let response = app
@@ -259,15 +601,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rewards_shutter() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: "0xdde52de1d892ccc671dcca55504803f87a2297089fd728ef2076af4c1b96ac1c"
                 .to_string(),
             voter_address: "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd".to_string(),
-            boosts: vec![
+            boosts: Some(vec![
                 ("44".to_string(), "11155111".to_string()),
                 ("45".to_string(), "11155111".to_string()),
-            ],
+            ]),
         };
 
         let response = app
@@ -298,15 +640,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rewards_shutter_and_ranked_choice() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: "0xfcdb01284958142a481fb4d579aa056ed93c29a9f58fbefbfb0504b3c1c06e96"
                 .to_string(),
             voter_address: "0xc83A9e69012312513328992d454290be85e95101".to_string(),
-            boosts: vec![
+            boosts: Some(vec![
                 ("46".to_string(), "11155111".to_string()),
                 ("47".to_string(), "11155111".to_string()),
-            ],
+            ]),
         };
 
         let response = app
@@ -337,12 +679,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_rewards_shutter_and_ranked_proportional() {
-        let app = super::app();
+        let app = crate::test_harness::app().await;
         let query = QueryParams {
             proposal_id: "0xe175412d46744bdb68e61c89492a5d3ebb55a487cf8fc4d35a0d671302babed3"
                 .to_string(),
             voter_address: "0xc83A9e69012312513328992d454290be85e95101".to_string(),
-            boosts: vec![("49".to_string(), "11155111".to_string())],
+            boosts: Some(vec![("49".to_string(), "11155111".to_string())]),
         };
 
         let response = app
@@ -370,4 +712,512 @@ mod tests {
             assert_eq!(result[0].boost_id, "49");
         }
     }
+
+    #[tokio::test]
+    async fn test_get_rewards_sql_injection() {
+        let app = crate::test_harness::app().await;
+        let query = QueryParams {
+            proposal_id: format!("{}' OR '1'='1", PROPOSAL_ID),
+            voter_address: WINNER.to_string(),
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
+        };
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/get-rewards")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "invalid_input");
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid proposal id"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_request() {
+        let app = crate::test_harness::app().await;
+        // `voter_address` is missing
+        let query = json!({ "proposal_id": PROPOSAL_ID });
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/get-rewards")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "invalid_input");
+    }
+
+    #[tokio::test]
+    async fn test_claim_window_expired() {
+        let app = crate::test_harness::app().await;
+        let query = QueryParams {
+            proposal_id: "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb"
+                .to_string(),
+            voter_address: "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd".to_string(),
+            boosts: Some(vec![("48".to_string(), "11155111".to_string())]),
+        };
+        let request = |uri: &str| {
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri(uri)
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                .unwrap()
+        };
+
+        // The reward is still reported, along with its deadline
+        let response = app.clone().oneshot(request("/get-rewards")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<GetRewardsResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].boost_id, "48");
+        assert_eq!(result[0].deadline, crate::test_harness::EXPIRED_CLAIM_END);
+
+        // But no voucher gets signed
+        let response = app.oneshot(request("/create-vouchers")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::CONFLICT);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "outside_claim_window");
+    }
+
+    #[tokio::test]
+    async fn test_explain_rewards_ranked_choice() {
+        let app = crate::test_harness::app().await;
+        let query = QueryParams {
+            proposal_id: "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb"
+                .to_string(),
+            voter_address: "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd".to_string(),
+            boosts: Some(vec![
+                ("42".to_string(), "11155111".to_string()),
+                ("43".to_string(), "11155111".to_string()),
+            ]),
+        };
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/explain-rewards")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<ExplainRewardsResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(result.len(), 2);
+
+        // Bribes are not allowed on ranked choice proposals
+        assert_eq!(result[0].boost_id, "42");
+        assert!(result[0].reward.is_none());
+        assert_eq!(
+            result[0].reason.as_ref().unwrap().code,
+            "boost_misconfigured"
+        );
+
+        assert_eq!(result[1].boost_id, "43");
+        assert_eq!(result[1].reward.as_deref(), Some("15000000000000000000"));
+        assert!(result[1].reason.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_explain_rewards_solvency() {
+        let ranked = "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb";
+        let explain = |voter: &str, boost_id: &str| {
+            let query = QueryParams {
+                proposal_id: ranked.to_string(),
+                voter_address: voter.to_string(),
+                boosts: Some(vec![(boost_id.to_string(), "11155111".to_string())]),
+            };
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri("/explain-rewards")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                .unwrap()
+        };
+
+        // Boost 50 has been emptied
+        let app = crate::test_harness::app().await;
+        let response = app
+            .oneshot(explain("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", "50"))
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<ExplainRewardsResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(result[0].reason.as_ref().unwrap().code, "insufficient_pool");
+
+        // This voter already claimed boost 43
+        let app = crate::test_harness::app().await;
+        let response = app
+            .oneshot(explain("0xc83A9e69012312513328992d454290be85e95101", "43"))
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<ExplainRewardsResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert!(result[0].reward.is_none());
+        assert_eq!(result[0].reason.as_ref().unwrap().code, "already_claimed");
+    }
+
+    #[tokio::test]
+    async fn test_claim_status() {
+        let app = crate::test_harness::app().await;
+        let query = QueryParams {
+            proposal_id: "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb"
+                .to_string(),
+            voter_address: "0xc83A9e69012312513328992d454290be85e95101".to_string(),
+            boosts: Some(vec![
+                ("42".to_string(), "11155111".to_string()),
+                ("43".to_string(), "11155111".to_string()),
+                ("44".to_string(), "11155111".to_string()),
+            ]),
+        };
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/claim-status")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: Vec<ClaimStatusResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(result.len(), 3);
+
+        assert_eq!(result[0].boost_id, "42");
+        assert!(result[0].entitlement.is_none());
+        assert_eq!(
+            result[0].reason.as_ref().unwrap().code,
+            "boost_misconfigured"
+        );
+        assert!(!result[0].claimed);
+
+        // Already claimed rewards are still reported, along with their claim
+        assert_eq!(result[1].boost_id, "43");
+        assert_eq!(
+            result[1].entitlement.as_deref(),
+            Some("15000000000000000000")
+        );
+        assert!(result[1].claimed);
+        let claim = result[1].claim.as_ref().unwrap();
+        assert_eq!(claim.amount, "15000000000000000000");
+        assert_eq!(
+            claim.transaction_hash,
+            crate::test_harness::CLAIM_TRANSACTION.parse().unwrap()
+        );
+        assert_eq!(claim.timestamp, crate::test_harness::CLAIM_TIMESTAMP);
+
+        // Boost 44 is not on this proposal
+        assert_eq!(result[2].boost_id, "44");
+        assert_eq!(result[2].reason.as_ref().unwrap().code, "invalid_input");
+        assert!(!result[2].claimed);
+        assert!(result[2].claim.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_claimable_rewards() {
+        let app = crate::test_harness::app().await;
+        let query = json!({ "voter_address": "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd" });
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/claimable-rewards")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let result: ClaimableResponse = serde_json::from_slice(&bytes).unwrap();
+
+        // Boosts 42 (misconfigured), 48 (expired) and 50 (emptied) are left out, and the lottery
+        // of boost 3 was not won
+        let boosts: Vec<_> = result
+            .rewards
+            .iter()
+            .map(|reward| reward.boost_id.as_str())
+            .collect();
+        assert_eq!(boosts, vec!["43", "45", "49", "47"]);
+        assert!(result
+            .rewards
+            .iter()
+            .all(|reward| reward.reward == "15000000000000000000"));
+
+        assert_eq!(result.totals.len(), 1);
+        assert_eq!(result.totals[0].chain_id, "11155111");
+        assert_eq!(
+            result.totals[0].token,
+            crate::test_harness::TOKEN.parse().unwrap()
+        );
+        assert_eq!(result.totals[0].amount, "60000000000000000000");
+    }
+
+    #[tokio::test]
+    async fn test_admin_caches() {
+        let app = crate::test_harness::app().await;
+        let request = |method, uri: &str, token: Option<&str>, body: Value| {
+            let mut builder = http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+            if let Some(token) = token {
+                builder = builder.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            builder
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
+        let token = Some(crate::test_harness::ADMIN_TOKEN);
+
+        // Only with the admin token
+        for token in [None, Some("wrong-token")] {
+            let response = app
+                .clone()
+                .oneshot(request(
+                    http::Method::GET,
+                    "/admin/caches",
+                    token,
+                    json!({}),
+                ))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+        }
+
+        // Caches the vote of the winner
+        let query = QueryParams {
+            proposal_id: PROPOSAL_ID.to_string(),
+            voter_address: WINNER.to_string(),
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
+        };
+        let response = app
+            .clone()
+            .oneshot(request(
+                http::Method::POST,
+                "/get-rewards",
+                None,
+                serde_json::to_value(&query).unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                http::Method::GET,
+                "/admin/caches",
+                token,
+                json!({}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let stats: Vec<CacheStats> = serde_json::from_slice(&bytes).unwrap();
+        let names: Vec<&str> = stats.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "proposal_info",
+                "vote_info",
+                "allocation",
+                "lottery_winners",
+                "merkle_distribution"
+            ]
+        );
+        assert!(stats[1].size >= 1);
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                http::Method::POST,
+                "/admin/caches/inspect",
+                token,
+                json!({
+                    "cache": "vote_info",
+                    "proposal_id": PROPOSAL_ID,
+                    "voter_address": WINNER.to_lowercase(),
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let entries: Vec<CacheEntry> = serde_json::from_slice(&bytes).unwrap();
+        assert!(!entries.is_empty());
+        assert_eq!(entries[0].value["choice"], "1");
+
+        // Evicting needs a target
+        let response = app
+            .oneshot(request(
+                http::Method::POST,
+                "/admin/caches/evict",
+                token,
+                json!({ "cache": "vote_info" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let app = crate::test_harness::app().await;
+        let post = |uri: &str, query: QueryParams| {
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri(uri)
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                .unwrap()
+        };
+
+        let query = QueryParams {
+            proposal_id: PROPOSAL_ID.to_string(),
+            voter_address: WINNER.to_string(),
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
+        };
+        let response = app
+            .clone()
+            .oneshot(post("/create-vouchers", query))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+
+        // Boost 50 has been emptied: it is skipped
+        let query = QueryParams {
+            proposal_id: "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb"
+                .to_string(),
+            voter_address: "0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd".to_string(),
+            boosts: Some(vec![("50".to_string(), CHAIN_ID.to_string())]),
+        };
+        let response = app
+            .clone()
+            .oneshot(post("/get-rewards", query))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .uri("/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let metrics = String::from_utf8(bytes.to_vec()).unwrap();
+
+        for expected in [
+            r#"boost_guard_http_requests_total{route="/create-vouchers",status="200"}"#,
+            r#"boost_guard_http_request_duration_seconds_count{route="/get-rewards"}"#,
+            r#"boost_guard_vouchers_signed_total{boost_id="3",chain_id="11155111",token="#,
+            r#"boost_guard_reward_signed_total{chain_id="11155111",token="#,
+            r#"boost_guard_upstream_duration_seconds_count{operation="boost",upstream="subgraph"}"#,
+            r#"boost_guard_boosts_skipped_total{reason="insufficient_pool"}"#,
+            r#"boost_guard_cache_hits{cache="vote_info"}"#,
+            r#"boost_guard_cache_misses{cache="allocation"}"#,
+        ] {
+            assert!(metrics.contains(expected), "missing {}", expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_vouchers() {
+        let app = crate::test_harness::app().await;
+        let query = QueryParams {
+            proposal_id: PROPOSAL_ID.to_string(),
+            voter_address: WINNER.to_string(),
+            boosts: Some(vec![(BOOST_ID.to_string(), CHAIN_ID.to_string())]),
+        };
+        let response = app
+            .clone()
+            .oneshot(
+                http::Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/create-vouchers")
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_vec(&query).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let vouchers: Vec<CreateVouchersResponse> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(vouchers.len(), 1);
+
+        let audit = |token: &str| {
+            http::Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/vouchers")
+                .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "recipient": WINNER,
+                        "boost_id": BOOST_ID,
+                        "chain_id": CHAIN_ID,
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(audit("wrong-token")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(audit(crate::test_harness::ADMIN_TOKEN))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let entries: Vec<AuditEntry> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].voucher.signature, vouchers[0].signature);
+        assert_eq!(entries[0].voucher.amount, vouchers[0].reward);
+        assert_eq!(entries[0].voucher.proposal_id, PROPOSAL_ID);
+        assert_eq!(entries[0].voucher.scores_state, "final");
+    }
 }
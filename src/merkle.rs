@@ -0,0 +1,143 @@
+//! Merkle tree over the `(recipient, amount)` distribution of a boost.
+//!
+//! Leaves and nodes are hashed like OpenZeppelin's `StandardMerkleTree`, so that proofs can be
+//! verified onchain with `MerkleProof.verify`: a leaf is `keccak256(keccak256(abi.encode(recipient,
+//! amount)))`, and the two children of a node are sorted before being hashed together.
+
+use ethers::abi::{encode, Token};
+use ethers::types::{Address, H256, U256};
+use ethers::utils::keccak256;
+
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    // Hashes of every level, from the sorted leaves up to the root
+    layers: Vec<Vec<H256>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree of `leaves`. Returns `None` if there is no leaf.
+    pub fn new(leaves: &[(Address, U256)]) -> Option<Self> {
+        if leaves.is_empty() {
+            return None;
+        }
+
+        let mut hashes: Vec<H256> = leaves
+            .iter()
+            .map(|(recipient, amount)| leaf_hash(*recipient, *amount))
+            .collect();
+        hashes.sort();
+
+        let mut layers = vec![hashes];
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(*left, *right),
+                    // The last node of an odd level is promoted as is
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+
+        Some(Self { layers })
+    }
+
+    pub fn root(&self) -> H256 {
+        self.layers.last().unwrap()[0]
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Returns the proof of the leaf `(recipient, amount)`, or `None` if it is not in the tree.
+    pub fn proof(&self, recipient: Address, amount: U256) -> Option<Vec<H256>> {
+        let leaf = leaf_hash(recipient, amount);
+        let mut index = self.layers[0].binary_search(&leaf).ok()?;
+
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+/// Checks `proof` the way `MerkleProof.verify` does.
+pub fn verify(root: H256, recipient: Address, amount: U256, proof: &[H256]) -> bool {
+    let computed = proof
+        .iter()
+        .fold(leaf_hash(recipient, amount), |node, sibling| {
+            node_hash(node, *sibling)
+        });
+    computed == root
+}
+
+fn leaf_hash(recipient: Address, amount: U256) -> H256 {
+    let encoded = encode(&[Token::Address(recipient), Token::Uint(amount)]);
+    H256::from(keccak256(keccak256(encoded)))
+}
+
+fn node_hash(a: H256, b: H256) -> H256 {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    H256::from(keccak256([left.as_bytes(), right.as_bytes()].concat()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u64) -> Vec<(Address, U256)> {
+        (1..=n)
+            .map(|i| (Address::from_low_u64_be(i), U256::from(i * 1000)))
+            .collect()
+    }
+
+    #[test]
+    fn test_every_proof_verifies() {
+        for n in [1, 2, 3, 4, 5, 8, 13] {
+            let leaves = leaves(n);
+            let tree = MerkleTree::new(&leaves).unwrap();
+            assert_eq!(tree.num_leaves(), n as usize);
+
+            for (recipient, amount) in &leaves {
+                let proof = tree.proof(*recipient, *amount).unwrap();
+                assert!(verify(tree.root(), *recipient, *amount, &proof));
+                // The proof is bound to the amount
+                assert!(!verify(tree.root(), *recipient, *amount + 1, &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_leaf() {
+        let recipient = Address::from_low_u64_be(1);
+        let tree = MerkleTree::new(&[(recipient, U256::from(10))]).unwrap();
+        assert_eq!(tree.root(), leaf_hash(recipient, U256::from(10)));
+        assert!(tree.proof(recipient, U256::from(10)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_leaf() {
+        let tree = MerkleTree::new(&leaves(3)).unwrap();
+        assert!(tree
+            .proof(Address::from_low_u64_be(1), U256::from(1))
+            .is_none());
+        assert!(MerkleTree::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_root_does_not_depend_on_order() {
+        let mut leaves = leaves(5);
+        let root = MerkleTree::new(&leaves).unwrap().root();
+        leaves.reverse();
+        assert_eq!(MerkleTree::new(&leaves).unwrap().root(), root);
+    }
+}
@@ -0,0 +1,214 @@
+//! Prometheus metrics, exposed at `/metrics`.
+//!
+//! Requests are measured per route, upstream calls (hub, subgraph, beacon) per operation. Cache
+//! statistics are read from the caches when the metrics are scraped.
+
+use crate::admin::cache_stats;
+use crate::{ServerError, State};
+use axum::extract::{MatchedPath, Request};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use ethers::types::{Address, U256};
+use prometheus::{
+    register_counter_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    CounterVec, Encoder, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::future::Future;
+use std::time::Instant;
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "boost_guard_http_requests_total",
+        "Requests handled, by route and status",
+        &["route", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "boost_guard_http_request_duration_seconds",
+        "Time to handle a request, by route",
+        &["route"]
+    )
+    .unwrap();
+    static ref VOUCHERS_SIGNED: IntCounterVec = register_int_counter_vec!(
+        "boost_guard_vouchers_signed_total",
+        "Vouchers signed, by chain, boost and token",
+        &["chain_id", "boost_id", "token"]
+    )
+    .unwrap();
+    static ref REWARD_SIGNED: CounterVec = register_counter_vec!(
+        "boost_guard_reward_signed_total",
+        "Rewards signed in vouchers, in the smallest unit of the token",
+        &["chain_id", "token"]
+    )
+    .unwrap();
+    static ref VOUCHER_CONFLICTS: IntCounterVec = register_int_counter_vec!(
+        "boost_guard_voucher_conflicts_total",
+        "Rewards recomputed to another amount than the voucher issued, by chain and boost",
+        &["chain_id", "boost_id"]
+    )
+    .unwrap();
+    static ref CACHE_HITS: IntGaugeVec = register_int_gauge_vec!(
+        "boost_guard_cache_hits",
+        "Lookups answered by the cache since the start",
+        &["cache"]
+    )
+    .unwrap();
+    static ref CACHE_MISSES: IntGaugeVec = register_int_gauge_vec!(
+        "boost_guard_cache_misses",
+        "Lookups missed by the cache since the start",
+        &["cache"]
+    )
+    .unwrap();
+    static ref UPSTREAM_DURATION: HistogramVec = register_histogram_vec!(
+        "boost_guard_upstream_duration_seconds",
+        "Time to get an answer from the hub, the subgraph or the beacon node",
+        &["upstream", "operation"]
+    )
+    .unwrap();
+    static ref UPSTREAM_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "boost_guard_upstream_errors_total",
+        "Failed calls to the hub, the subgraph or the beacon node",
+        &["upstream", "operation"]
+    )
+    .unwrap();
+    static ref BOOSTS_SKIPPED: IntCounterVec = register_int_counter_vec!(
+        "boost_guard_boosts_skipped_total",
+        "Boosts left out of the rewards of a voter, by error code",
+        &["reason"]
+    )
+    .unwrap();
+}
+
+pub async fn handle_metrics(
+    Extension(state): Extension<State>,
+) -> Result<impl IntoResponse, ServerError> {
+    for stats in cache_stats(&state.store).await? {
+        CACHE_HITS
+            .with_label_values(&[&stats.name])
+            .set(stats.hits as i64);
+        CACHE_MISSES
+            .with_label_values(&[&stats.name])
+            .set(stats.misses as i64);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|e| ServerError::Internal(format!("metrics: {}", e)))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    ))
+}
+
+/// Middleware counting and timing the requests of every route.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    // The route template rather than the uri, so that the labels stay bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&route])
+        .observe(start.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[&route, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// Times a call to an upstream, and counts it if it fails.
+pub async fn observe_upstream<T>(
+    upstream: &str,
+    operation: &str,
+    call: impl Future<Output = Result<T, ServerError>>,
+) -> Result<T, ServerError> {
+    let start = Instant::now();
+    let result = call.await;
+
+    UPSTREAM_DURATION
+        .with_label_values(&[upstream, operation])
+        .observe(start.elapsed().as_secs_f64());
+    if result.is_err() {
+        UPSTREAM_ERRORS
+            .with_label_values(&[upstream, operation])
+            .inc();
+    }
+    result
+}
+
+pub fn record_voucher(chain_id: &str, boost_id: &str, token: &Address, reward: U256) {
+    let token = format!("{:?}", token);
+    VOUCHERS_SIGNED
+        .with_label_values(&[chain_id, boost_id, &token])
+        .inc();
+    // Only an approximation above 2^53, which is fine for a dashboard
+    REWARD_SIGNED
+        .with_label_values(&[chain_id, &token])
+        .inc_by(u256_to_f64(reward));
+}
+
+pub fn record_voucher_conflict(chain_id: &str, boost_id: &str) {
+    VOUCHER_CONFLICTS
+        .with_label_values(&[chain_id, boost_id])
+        .inc();
+}
+
+pub fn record_skipped_boost(error: &ServerError) {
+    BOOSTS_SKIPPED.with_label_values(&[error.code()]).inc();
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_observe_upstream() {
+        let errors = || {
+            UPSTREAM_ERRORS
+                .with_label_values(&["test", "observe"])
+                .get()
+        };
+        let calls = || {
+            UPSTREAM_DURATION
+                .with_label_values(&["test", "observe"])
+                .get_sample_count()
+        };
+
+        let ok = observe_upstream("test", "observe", async { Ok(1) }).await;
+        assert_eq!(ok, Ok(1));
+        assert_eq!((calls(), errors()), (1, 0));
+
+        let failed = observe_upstream::<u32>("test", "observe", async {
+            Err(ServerError::UpstreamUnavailable("down".to_string()))
+        })
+        .await;
+        assert!(failed.is_err());
+        assert_eq!((calls(), errors()), (2, 1));
+    }
+
+    #[test]
+    fn test_u256_to_f64() {
+        assert_eq!(u256_to_f64(U256::zero()), 0.0);
+        assert_eq!(u256_to_f64(U256::from(42)), 42.0);
+        assert_eq!(u256_to_f64(U256::exp10(18)), 1e18);
+        assert_eq!(u256_to_f64(U256::exp10(30)), 1e30);
+    }
+}
@@ -1,9 +1,10 @@
+use crate::config::DeploymentConfig;
 use crate::routes::RewardInfo;
-use crate::{ServerError, BOOST_NAME, BOOST_VERSION, VERIFYING_CONTRACT};
+use crate::ServerError;
 use ethers::signers::LocalWallet;
 use ethers::types::{
     transaction::eip712::{Eip712, TypedData},
-    Address, Signature, U256,
+    Address, Signature, H256, U256,
 };
 
 #[derive(Debug, Clone)]
@@ -24,36 +25,35 @@ impl ClaimConfig {
         recipient: &str,
         amount: &str,
     ) -> Result<Self, ServerError> {
+        let invalid = |field: &str| ServerError::Internal(format!("invalid claim {}", field));
         Ok(Self {
-            boost_id: U256::from_str_radix(boost_id, 10)?,
-            chain_id: U256::from_str_radix(chain_id, 10)?,
-            recipient: recipient.parse()?,
-            amount: U256::from_str_radix(amount, 10)?,
+            boost_id: U256::from_str_radix(boost_id, 10).map_err(|_| invalid("boost id"))?,
+            chain_id: U256::from_str_radix(chain_id, 10).map_err(|_| invalid("chain id"))?,
+            recipient: recipient.parse().map_err(|_| invalid("recipient"))?,
+            amount: U256::from_str_radix(amount, 10).map_err(|_| invalid("amount"))?,
         })
     }
 
-    pub fn create_signature(&self, signer: &LocalWallet) -> Result<Signature, ServerError> {
-        let json = serde_json::json!( {
-          "types": {
-            "EIP712Domain": [
-              {
-                "name": "name",
-                "type": "string"
-              },
-              {
-                "name": "version",
-                "type": "string"
-              },
-              {
-                "name": "chainId",
-                "type": "uint256"
-              },
-              {
-                "name": "verifyingContract",
-                "type": "address"
-              }
-            ],
-            "Claim": [
+    /// Signs the claim with the EIP712 domain of the boost contract `deployment`.
+    pub fn create_signature(
+        &self,
+        signer: &LocalWallet,
+        deployment: &DeploymentConfig,
+    ) -> Result<Signature, ServerError> {
+        sign(signer, self.typed_data(deployment))
+    }
+
+    /// EIP712 digest of the claim, as signed by `create_signature`.
+    pub fn digest(&self, deployment: &DeploymentConfig) -> H256 {
+        digest(self.typed_data(deployment))
+    }
+
+    fn typed_data(&self, deployment: &DeploymentConfig) -> TypedData {
+        typed_data(
+            deployment,
+            self.chain_id,
+            "Claim",
+            serde_json::json!([
               {
                   "name": "boostId",
                   "type": "uint256"
@@ -66,29 +66,124 @@ impl ClaimConfig {
                   "name": "amount",
                   "type": "uint256"
               }
-            ]
+            ]),
+            serde_json::json!({
+              "boostId": self.boost_id.to_string(),
+              "recipient": format!("{:?}", self.recipient),
+              "amount": self.amount.to_string(),
+            }),
+        )
+    }
+}
+
+/// Root of the Merkle tree of a boost distribution, signed once instead of one claim per voter.
+#[derive(Debug, Clone)]
+pub struct MerkleRootConfig {
+    boost_id: U256,
+    chain_id: U256,
+    root: H256,
+}
+
+impl MerkleRootConfig {
+    pub fn new(boost_id: U256, chain_id: U256, root: H256) -> Self {
+        Self {
+            boost_id,
+            chain_id,
+            root,
+        }
+    }
+
+    /// Signs the root with the EIP712 domain of the boost contract `deployment`.
+    pub fn create_signature(
+        &self,
+        signer: &LocalWallet,
+        deployment: &DeploymentConfig,
+    ) -> Result<Signature, ServerError> {
+        sign(signer, self.typed_data(deployment))
+    }
+
+    /// EIP712 digest of the root, as signed by `create_signature`.
+    pub fn digest(&self, deployment: &DeploymentConfig) -> H256 {
+        digest(self.typed_data(deployment))
+    }
+
+    fn typed_data(&self, deployment: &DeploymentConfig) -> TypedData {
+        typed_data(
+            deployment,
+            self.chain_id,
+            "MerkleRoot",
+            serde_json::json!([
+              {
+                  "name": "boostId",
+                  "type": "uint256"
+              },
+              {
+                  "name": "root",
+                  "type": "bytes32"
+              }
+            ]),
+            serde_json::json!({
+              "boostId": self.boost_id.to_string(),
+              "root": format!("{:?}", self.root),
+            }),
+        )
+    }
+}
+
+// Builds `message`, of type `primary_type` with the given `fields`, for the domain of `deployment`.
+fn typed_data(
+    deployment: &DeploymentConfig,
+    chain_id: U256,
+    primary_type: &str,
+    fields: serde_json::Value,
+    message: serde_json::Value,
+) -> TypedData {
+    let json = serde_json::json!( {
+      "types": {
+        "EIP712Domain": [
+          {
+            "name": "name",
+            "type": "string"
           },
-          "primaryType": "Claim",
-          "domain": {
-            "name": BOOST_NAME.as_str(),
-            "version": BOOST_VERSION.as_str(),
-            "chainId": self.chain_id,
-            "verifyingContract": VERIFYING_CONTRACT.as_str(),
+          {
+            "name": "version",
+            "type": "string"
           },
-          "message": {
-            "boostId": self.boost_id.to_string(),
-            "recipient": format!("{:?}", self.recipient),
-            "amount": self.amount.to_string(),
+          {
+            "name": "chainId",
+            "type": "uint256"
+          },
+          {
+            "name": "verifyingContract",
+            "type": "address"
           }
-        });
+        ],
+        primary_type: fields,
+      },
+      "primaryType": primary_type,
+      "domain": {
+        "name": deployment.eip712_name,
+        "version": deployment.eip712_version,
+        "chainId": chain_id,
+        "verifyingContract": format!("{:?}", deployment.verifying_contract),
+      },
+      "message": message,
+    });
 
-        let typed_data: TypedData = serde_json::from_value(json).expect("invalid json");
-        let digest = typed_data.encode_eip712().expect("failed to encode eip712");
+    serde_json::from_value(json).expect("invalid json")
+}
 
-        signer
-            .sign_hash(digest.into())
-            .map_err(|e| ServerError::ErrorString(e.to_string()))
-    }
+fn digest(typed_data: TypedData) -> H256 {
+    typed_data
+        .encode_eip712()
+        .expect("failed to encode eip712")
+        .into()
+}
+
+fn sign(signer: &LocalWallet, typed_data: TypedData) -> Result<Signature, ServerError> {
+    signer
+        .sign_hash(digest(typed_data))
+        .map_err(|e| ServerError::Internal(e.to_string()))
 }
 
 impl TryFrom<&RewardInfo> for ClaimConfig {
@@ -106,8 +201,11 @@ impl TryFrom<&RewardInfo> for ClaimConfig {
 
 #[cfg(test)]
 mod tests {
-    use super::ClaimConfig;
-    use ethers::types::U256;
+    use super::{ClaimConfig, MerkleRootConfig};
+    use crate::config::DeploymentConfig;
+    use ethers::signers::Signer;
+    use ethers::types::transaction::eip712::Eip712;
+    use ethers::types::{H256, U256};
     use std::{env, str::FromStr};
 
     #[test]
@@ -117,12 +215,14 @@ mod tests {
             "PRIVATE_KEY",
             "0xafdfd9c3d2095ef696594f6cedcae59e72dcd697e2a7521b1578140422a4f890",
         );
-        std::env::set_var("BOOST_NAME", "boost");
-        std::env::set_var("BOOST_VERSION", "1");
-        std::env::set_var(
-            "VERIFYING_CONTRACT",
-            "0x3a18420C0646CC8e6D46E43d792335AeCB657fd0",
-        );
+        let deployment = DeploymentConfig {
+            subgraph_url: "http://localhost".to_string(),
+            verifying_contract: "0x3a18420C0646CC8e6D46E43d792335AeCB657fd0"
+                .parse()
+                .unwrap(),
+            eip712_name: "boost".to_string(),
+            eip712_version: "1".to_string(),
+        };
 
         let claim_cfg = ClaimConfig {
             boost_id: U256::from(24),
@@ -137,7 +237,40 @@ mod tests {
         let wallet = ethers::signers::LocalWallet::from_str(&private_key)
             .expect("failed to create a local wallet");
 
-        let sig = claim_cfg.create_signature(&wallet).unwrap();
+        let sig = claim_cfg.create_signature(&wallet, &deployment).unwrap();
         assert!(sig.to_string() == "e299620773c7aa0ef7c715cd005eb48d0eacd8f6809bfa4505c96d7028b75d4931bdba5098e89259c97b2b059f9baea13e75a0ffe2d9379bbebbcfb5b8a932e01c");
     }
+
+    #[test]
+    fn test_merkle_root_sig() {
+        let deployment = DeploymentConfig {
+            subgraph_url: "http://localhost".to_string(),
+            verifying_contract: "0x3a18420C0646CC8e6D46E43d792335AeCB657fd0"
+                .parse()
+                .unwrap(),
+            eip712_name: "boost".to_string(),
+            eip712_version: "1".to_string(),
+        };
+        let wallet = ethers::signers::LocalWallet::from_str(
+            "0xafdfd9c3d2095ef696594f6cedcae59e72dcd697e2a7521b1578140422a4f890",
+        )
+        .unwrap();
+
+        let root_cfg = MerkleRootConfig::new(
+            U256::from(24),
+            U256::from(11155111),
+            H256::from_low_u64_be(42),
+        );
+        let sig = root_cfg.create_signature(&wallet, &deployment).unwrap();
+        let digest = root_cfg.typed_data(&deployment).encode_eip712().unwrap();
+        assert_eq!(sig.recover(H256::from(digest)).unwrap(), wallet.address());
+
+        // The signature is bound to the root
+        let other = MerkleRootConfig::new(
+            U256::from(24),
+            U256::from(11155111),
+            H256::from_low_u64_be(43),
+        );
+        assert_ne!(other.create_signature(&wallet, &deployment).unwrap(), sig);
+    }
 }
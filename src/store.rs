@@ -0,0 +1,214 @@
+//! Persistent store of finalized artifacts.
+//!
+//! Once a proposal is final, neither its votes nor the rewards of its boosts can change anymore:
+//! they are kept in SQLite so that a restart does not fetch and compute them again. The lottery
+//! of a boost is therefore drawn once and kept forever. The in-memory caches stay in front of the
+//! store.
+//!
+//! The store is read from `STORE_PATH`. When it is not set, the store only lives in memory.
+
+use crate::ServerError;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Kinds of artifacts, each one with its own keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    // Final proposal, keyed by proposal id
+    Proposal,
+    // Vote on a final proposal, keyed by voter and proposal id
+    Vote,
+    // Rewards of an even or weighted boost, keyed by boost
+    Allocation,
+    // Rewards of the winners of a lottery, keyed by boost
+    LotteryWinners,
+    // Seed the winners of a lottery were drawn with, keyed by boost
+    LotterySeed,
+}
+
+impl Artifact {
+    fn as_str(self) -> &'static str {
+        match self {
+            Artifact::Proposal => "proposal",
+            Artifact::Vote => "vote",
+            Artifact::Allocation => "allocation",
+            Artifact::LotteryWinners => "lottery_winners",
+            Artifact::LotterySeed => "lottery_seed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if needed.
+    pub fn open(path: &str) -> Result<Self, ServerError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A store that is lost when the process exits.
+    pub fn in_memory() -> Result<Self, ServerError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Opens the store at `STORE_PATH`, or an in-memory one if it is not set.
+    pub fn from_env() -> Result<Self, ServerError> {
+        match std::env::var("STORE_PATH") {
+            Ok(path) => Self::open(&path),
+            Err(_) => {
+                tracing::warn!("STORE_PATH is not set: finalized results will not persist");
+                Self::in_memory()
+            }
+        }
+    }
+
+    fn init(conn: Connection) -> Result<Self, ServerError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS artifacts (
+                kind TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (kind, key)
+            )",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn get<K: Serialize, V: DeserializeOwned>(
+        &self,
+        artifact: Artifact,
+        key: &K,
+    ) -> Result<Option<V>, ServerError> {
+        let key = encode(key)?;
+        let value: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM artifacts WHERE kind = ?1 AND key = ?2",
+                params![artifact.as_str(), key],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        value
+            .map(|value| {
+                serde_json::from_str(&value)
+                    .map_err(|e| ServerError::Internal(format!("store: {}", e)))
+            })
+            .transpose()
+    }
+
+    /// Stores `value`. Artifacts are final: a stored value is never replaced.
+    pub fn put<K: Serialize, V: Serialize>(
+        &self,
+        artifact: Artifact,
+        key: &K,
+        value: &V,
+    ) -> Result<(), ServerError> {
+        let key = encode(key)?;
+        let value = encode(value)?;
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO artifacts (kind, key, value) VALUES (?1, ?2, ?3)",
+            params![artifact.as_str(), key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Keys of every artifact of this kind.
+    pub fn keys<K: DeserializeOwned>(&self, artifact: Artifact) -> Result<Vec<K>, ServerError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT key FROM artifacts WHERE kind = ?1")?;
+        let keys = statement
+            .query_map(params![artifact.as_str()], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        keys.iter()
+            .map(|key| {
+                serde_json::from_str(key)
+                    .map_err(|e| ServerError::Internal(format!("store: {}", e)))
+            })
+            .collect()
+    }
+
+    /// Removes an artifact, when the data it was computed from got corrected. Returns whether it
+    /// was stored.
+    pub fn remove<K: Serialize>(&self, artifact: Artifact, key: &K) -> Result<bool, ServerError> {
+        let key = encode(key)?;
+        let removed = self.conn.lock().unwrap().execute(
+            "DELETE FROM artifacts WHERE kind = ?1 AND key = ?2",
+            params![artifact.as_str(), key],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Number of artifacts of this kind.
+    pub fn count(&self, artifact: Artifact) -> Result<usize, ServerError> {
+        let count: i64 = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM artifacts WHERE kind = ?1",
+            params![artifact.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<String, ServerError> {
+    serde_json::to_string(value).map_err(|e| ServerError::Internal(format!("store: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_put() {
+        let store = Store::in_memory().unwrap();
+        let key = ("0x3901D0fDe202aF1427216b79f5243f8A022d68cf", "0x11");
+
+        assert_eq!(store.get::<_, u64>(Artifact::Vote, &key).unwrap(), None);
+        store.put(Artifact::Vote, &key, &42_u64).unwrap();
+        assert_eq!(store.get(Artifact::Vote, &key).unwrap(), Some(42_u64));
+
+        // Final: the first value is kept
+        store.put(Artifact::Vote, &key, &43_u64).unwrap();
+        assert_eq!(store.get(Artifact::Vote, &key).unwrap(), Some(42_u64));
+
+        // Kinds do not share keys
+        assert_eq!(store.get::<_, u64>(Artifact::Proposal, &key).unwrap(), None);
+        assert_eq!(store.count(Artifact::Vote).unwrap(), 1);
+        assert_eq!(store.count(Artifact::Proposal).unwrap(), 0);
+
+        let keys: Vec<(String, String)> = store.keys(Artifact::Vote).unwrap();
+        assert_eq!(keys, vec![(key.0.to_string(), key.1.to_string())]);
+        assert!(store.remove(Artifact::Vote, &key).unwrap());
+        assert!(!store.remove(Artifact::Vote, &key).unwrap());
+        assert_eq!(store.get::<_, u64>(Artifact::Vote, &key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!("store-{}.sqlite", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        Store::open(path)
+            .unwrap()
+            .put(Artifact::Proposal, &"0x11", &"final")
+            .unwrap();
+        let reopened = Store::open(path).unwrap();
+        assert_eq!(
+            reopened.get(Artifact::Proposal, &"0x11").unwrap(),
+            Some("final".to_string())
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+use crate::config::Config;
+use crate::metrics;
+use crate::routes::BoostInfo;
+use crate::ServerError;
+use async_trait::async_trait;
+use ethers::types::{Address, H256, U256};
+use futures::future::join_all;
+use graphql_client::{GraphQLQuery, Response as GraphQLResponse};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+/// Source of the boosts created onchain.
+#[async_trait]
+pub trait BoostRegistry: std::fmt::Debug + Send + Sync {
+    /// Returns the boosts with id `boost_id` on chain `chain_id`, one per boost contract
+    /// deployment that has such a boost.
+    async fn boosts(&self, boost_id: &str, chain_id: &str) -> Result<Vec<BoostInfo>, ServerError>;
+
+    /// Returns every boost of the given proposals, across all chains. A chain that can not be
+    /// reached is left out, unless none can.
+    async fn proposal_boosts(
+        &self,
+        proposal_ids: &[String],
+    ) -> Result<Vec<ProposalBoost>, ServerError>;
+
+    /// Returns the claims made by `recipient` on the given boost.
+    async fn claims(
+        &self,
+        boost_info: &BoostInfo,
+        recipient: Address,
+    ) -> Result<Vec<ClaimInfo>, ServerError>;
+}
+
+/// A boost of a proposal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposalBoost {
+    pub proposal_id: String,
+    pub boost_id: String,
+    pub chain_id: String,
+}
+
+/// A claim of a boost reward, made onchain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimInfo {
+    pub recipient: Address,
+    pub amount: U256,
+    pub block_timestamp: u64,
+    pub transaction_hash: H256,
+}
+
+type Bytes = Address;
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/subgraph_schema.json",
+    query_path = "src/graphql/boost_query.graphql",
+    response_derives = "Debug"
+)]
+pub struct BoostQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/subgraph_schema.json",
+    query_path = "src/graphql/proposal_boosts_query.graphql",
+    response_derives = "Debug"
+)]
+pub struct ProposalBoostsQuery;
+
+// Claims hold transaction hashes, which do not fit in an `Address`: `Bytes` are kept as strings.
+mod claims {
+    use graphql_client::GraphQLQuery;
+
+    type Bytes = String;
+    type BigInt = String;
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/subgraph_schema.json",
+        query_path = "src/graphql/claims_query.graphql",
+        response_derives = "Debug"
+    )]
+    pub struct ClaimsQuery;
+}
+use claims::{claims_query, ClaimsQuery};
+
+// Number of proposals whose boosts are listed by a single subgraph query
+const PROPOSALS_PER_QUERY: usize = 100;
+
+/// Boost registry backed by the boost subgraphs, one per boost contract deployment.
+#[derive(Debug, Clone)]
+pub struct SubgraphRegistry {
+    client: reqwest::Client,
+    // (subgraph url, boost contract) of every deployment, indexed by chain id
+    deployments: HashMap<String, Vec<(String, Address)>>,
+}
+
+impl SubgraphRegistry {
+    pub fn new(
+        client: reqwest::Client,
+        deployments: HashMap<String, Vec<(String, Address)>>,
+    ) -> Self {
+        Self {
+            client,
+            deployments,
+        }
+    }
+
+    /// Creates a registry using the subgraph urls of the configured deployments.
+    pub fn from_config(client: reqwest::Client, config: &Config) -> Self {
+        let deployments = config
+            .chains
+            .iter()
+            .map(|chain| {
+                let deployments = chain
+                    .deployments
+                    .iter()
+                    .map(|d| (d.subgraph_url.clone(), d.verifying_contract))
+                    .collect();
+                (chain.chain_id.to_string(), deployments)
+            })
+            .collect();
+        Self::new(client, deployments)
+    }
+
+    // Queries the subgraph at `url`. Returns `None` if the boost does not exist on this deployment.
+    async fn query_boost(
+        &self,
+        url: &str,
+        boost_id: &str,
+        chain_id: &str,
+    ) -> Result<Option<BoostInfo>, ServerError> {
+        let variables = boost_query::Variables {
+            id: boost_id.to_owned(),
+        };
+
+        self.query::<BoostQuery>("boost", url, variables)
+            .await?
+            .boost
+            .map(|boost| {
+                BoostInfo::try_from((boost, chain_id))
+                    .map_err(|e| ServerError::BoostMisconfigured(e.to_string()))
+            })
+            .transpose()
+    }
+
+    // Queries the boosts of the given proposals on the subgraph at `url`. The subgraph caps the
+    // size of its answers: boosts are paged by id until a page comes back empty.
+    async fn deployment_boosts(
+        &self,
+        url: &str,
+        chain_id: &str,
+        proposal_ids: &[String],
+    ) -> Result<Vec<ProposalBoost>, ServerError> {
+        let mut boosts = Vec::new();
+        for proposals in proposal_ids.chunks(PROPOSALS_PER_QUERY) {
+            let mut after = String::new();
+            loop {
+                let variables = proposal_boosts_query::Variables {
+                    proposals: proposals.to_vec(),
+                    after,
+                };
+                let page = self
+                    .query::<ProposalBoostsQuery>("proposal_boosts", url, variables)
+                    .await?
+                    .boosts;
+                after = match page.last() {
+                    Some(boost) => boost.id.clone(),
+                    None => break,
+                };
+                boosts.extend(page.into_iter().filter_map(|boost| {
+                    Some(ProposalBoost {
+                        proposal_id: boost.strategy?.proposal,
+                        boost_id: boost.id,
+                        chain_id: chain_id.to_string(),
+                    })
+                }));
+            }
+        }
+        Ok(boosts)
+    }
+
+    // `operation` names the query in the metrics
+    async fn query<Q: GraphQLQuery>(
+        &self,
+        operation: &str,
+        url: &str,
+        variables: Q::Variables,
+    ) -> Result<Q::ResponseData, ServerError> {
+        let request_body = Q::build_query(variables);
+
+        metrics::observe_upstream("subgraph", operation, async {
+            let res = self.client.post(url).json(&request_body).send().await?;
+            let response_body: GraphQLResponse<Q::ResponseData> = res.json().await?;
+            response_body.data.ok_or_else(|| {
+                ServerError::UpstreamUnavailable("missing data from the graph".to_string())
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl BoostRegistry for SubgraphRegistry {
+    async fn boosts(&self, boost_id: &str, chain_id: &str) -> Result<Vec<BoostInfo>, ServerError> {
+        info!(?boost_id, ?chain_id, "get_boost_info");
+        let deployments = self.deployments.get(chain_id).ok_or_else(|| {
+            ServerError::InvalidInput(format!("no subgraph for chain {}", chain_id))
+        })?;
+
+        let mut boosts = Vec::new();
+        for (url, verifying_contract) in deployments {
+            if let Some(mut boost_info) = self.query_boost(url, boost_id, chain_id).await? {
+                boost_info.verifying_contract = *verifying_contract;
+                boosts.push(boost_info);
+            }
+        }
+        Ok(boosts)
+    }
+
+    async fn proposal_boosts(
+        &self,
+        proposal_ids: &[String],
+    ) -> Result<Vec<ProposalBoost>, ServerError> {
+        info!(num_proposals = proposal_ids.len(), "get_proposal_boosts");
+        let queries = self.deployments.iter().flat_map(|(chain_id, deployments)| {
+            deployments.iter().map(move |(url, _)| async move {
+                let boosts = self.deployment_boosts(url, chain_id, proposal_ids).await;
+                (chain_id, url, boosts)
+            })
+        });
+
+        // A subgraph that is down only hides the boosts of its deployment
+        let mut boosts = Vec::new();
+        let mut answered = false;
+        let mut failure = None;
+        for (chain_id, url, result) in join_all(queries).await {
+            match result {
+                Ok(found) => {
+                    answered = true;
+                    boosts.extend(found);
+                }
+                Err(error) => {
+                    warn!(chain_id, url, ?error, "skipping the boosts of a deployment");
+                    failure = Some(error);
+                }
+            }
+        }
+        if let (false, Some(error)) = (answered, failure) {
+            return Err(error);
+        }
+        Ok(sorted_boosts(boosts))
+    }
+
+    async fn claims(
+        &self,
+        boost_info: &BoostInfo,
+        recipient: Address,
+    ) -> Result<Vec<ClaimInfo>, ServerError> {
+        let chain_id = boost_info.chain_id.to_string();
+        let url = self
+            .deployments
+            .get(&chain_id)
+            .and_then(|deployments| {
+                deployments
+                    .iter()
+                    .find(|(_, contract)| *contract == boost_info.verifying_contract)
+            })
+            .map(|(url, _)| url)
+            .ok_or_else(|| {
+                ServerError::Internal(format!(
+                    "no subgraph for {:?} on chain {}",
+                    boost_info.verifying_contract, chain_id
+                ))
+            })?;
+
+        let variables = claims_query::Variables {
+            boost: boost_info.id.to_string(),
+            recipient: format!("{:?}", recipient),
+        };
+
+        let invalid = |field: &str| {
+            ServerError::UpstreamUnavailable(format!(
+                "failed to parse claim {} from the graph",
+                field
+            ))
+        };
+        self.query::<ClaimsQuery>("claims", url, variables)
+            .await?
+            .claims
+            .into_iter()
+            .map(|claim| {
+                Ok(ClaimInfo {
+                    recipient: claim.recipient.parse().map_err(|_| invalid("recipient"))?,
+                    amount: U256::from_dec_str(&claim.amount).map_err(|_| invalid("amount"))?,
+                    block_timestamp: claim
+                        .block_timestamp
+                        .parse()
+                        .map_err(|_| invalid("timestamp"))?,
+                    transaction_hash: claim
+                        .transaction_hash
+                        .parse()
+                        .map_err(|_| invalid("transaction hash"))?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Boost registry kept in memory. Useful for tests, or to embed the guard without a subgraph.
+#[derive(Debug, Default)]
+pub struct InMemoryRegistry {
+    // Boosts of every deployment, indexed by (boost_id, chain_id)
+    boosts: RwLock<HashMap<(String, String), Vec<BoostInfo>>>,
+    // Claims, indexed by (boost_id, chain_id, boost contract)
+    claims: RwLock<HashMap<(String, String, Address), Vec<ClaimInfo>>>,
+}
+
+impl InMemoryRegistry {
+    /// Records a boost. A previous boost with the same id on the same deployment gets replaced.
+    pub fn insert_boost(&self, boost_info: BoostInfo) {
+        let key = (boost_info.id.to_string(), boost_info.chain_id.to_string());
+        let mut boosts = self.boosts.write().unwrap();
+        let deployments = boosts.entry(key).or_default();
+        deployments.retain(|b| b.verifying_contract != boost_info.verifying_contract);
+        deployments.push(boost_info);
+    }
+
+    pub fn insert_claim(&self, boost_info: &BoostInfo, claim: ClaimInfo) {
+        self.claims
+            .write()
+            .unwrap()
+            .entry(claims_key(boost_info))
+            .or_default()
+            .push(claim);
+    }
+}
+
+// Sorts boosts by proposal, chain then id, dropping duplicates: boost ids are only unique per
+// deployment, and `find_boost` resolves the one on the right deployment.
+fn sorted_boosts(mut boosts: Vec<ProposalBoost>) -> Vec<ProposalBoost> {
+    boosts.sort_by_cached_key(|boost| {
+        (
+            boost.proposal_id.clone(),
+            boost.chain_id.parse::<u64>().unwrap_or_default(),
+            boost.boost_id.parse::<u64>().unwrap_or_default(),
+        )
+    });
+    boosts.dedup();
+    boosts
+}
+
+fn claims_key(boost_info: &BoostInfo) -> (String, String, Address) {
+    (
+        boost_info.id.to_string(),
+        boost_info.chain_id.to_string(),
+        boost_info.verifying_contract,
+    )
+}
+
+#[async_trait]
+impl BoostRegistry for InMemoryRegistry {
+    async fn boosts(&self, boost_id: &str, chain_id: &str) -> Result<Vec<BoostInfo>, ServerError> {
+        Ok(self
+            .boosts
+            .read()
+            .unwrap()
+            .get(&(boost_id.to_string(), chain_id.to_string()))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn proposal_boosts(
+        &self,
+        proposal_ids: &[String],
+    ) -> Result<Vec<ProposalBoost>, ServerError> {
+        let boosts = self
+            .boosts
+            .read()
+            .unwrap()
+            .values()
+            .flatten()
+            .filter(|boost| proposal_ids.contains(&boost.params.proposal))
+            .map(|boost| ProposalBoost {
+                proposal_id: boost.params.proposal.clone(),
+                boost_id: boost.id.to_string(),
+                chain_id: boost.chain_id.to_string(),
+            })
+            .collect();
+        Ok(sorted_boosts(boosts))
+    }
+
+    async fn claims(
+        &self,
+        boost_info: &BoostInfo,
+        recipient: Address,
+    ) -> Result<Vec<ClaimInfo>, ServerError> {
+        Ok(self
+            .claims
+            .read()
+            .unwrap()
+            .get(&claims_key(boost_info))
+            .map(|claims| {
+                claims
+                    .iter()
+                    .filter(|claim| claim.recipient == recipient)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,431 @@
+//! Local stand-ins for the hub database, the boost subgraphs and the beacon node, used by the
+//! end-to-end tests. The subgraph and the beacon node are served over HTTP on an ephemeral port
+//! so that the real `SubgraphRegistry` and `Beaconchain` clients get exercised.
+
+use axum::extract::{Path, State as AxumState};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use boost_guard::audit::AuditLog;
+use boost_guard::beacon::Beaconchain;
+use boost_guard::config::Config;
+use boost_guard::hub::InMemoryHub;
+use boost_guard::routes::{ProposalInfo, VoteWithChoice};
+use boost_guard::store::Store;
+use boost_guard::subgraph::SubgraphRegistry;
+use boost_guard::voting_power::VotingPower;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+// Test private key, whose address is `0x06a85356dcb5b307096726fb86a78c59d38e08ee`.
+pub const PRIVATE_KEY: &str = "0xafdfd9c3d2095ef696594f6cedcae59e72dcd697e2a7521b1578140422a4f890";
+pub const CHAIN_ID: &str = "11155111";
+pub const RANDAO_REVEAL: &str =
+    "0xa1f3cd2e5d8e2f4b6c7d8e9fa0b1c2d3e4f5061728394a5b6c7d8e9f0a1b2c00";
+
+const END: u64 = 1709820900;
+// Claim deadline of the boosts, far in the future
+const CLAIM_END: &str = "4102444800";
+// Claim deadline of boost 48, which has passed
+pub const EXPIRED_CLAIM_END: u64 = 1710425700;
+pub const TOKEN: &str = "0x7b79995e5f793a07bc00c21412e50ecae098e7f9";
+pub const ADMIN_TOKEN: &str = "admin-token";
+
+/// Builds the guard's router on top of a seeded hub, a fake subgraph and a fake beacon node.
+pub async fn app() -> Router {
+    let client = reqwest::Client::new();
+
+    let subgraph_url = serve(fake_subgraph()).await;
+    let config = config(&subgraph_url);
+
+    let beacon_url = serve(fake_beacon()).await;
+    let beacon = Beaconchain::new(
+        client.clone(),
+        &format!("{}/slot/", beacon_url),
+        &format!("{}/epoch/", beacon_url),
+        "",
+    );
+
+    let state = boost_guard::State {
+        hub: Arc::new(seeded_hub()),
+        registry: Arc::new(SubgraphRegistry::from_config(client, &config)),
+        beacon: Arc::new(beacon),
+        store: Arc::new(Store::in_memory().unwrap()),
+        audit: Arc::new(AuditLog::in_memory().unwrap()),
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        config: Arc::new(config),
+        wallet: ethers::signers::LocalWallet::from_str(PRIVATE_KEY).unwrap(),
+    };
+
+    crate::app(state)
+}
+
+// The boosts live on one chain, signing with the same EIP712 domain as the CI. The subgraph of
+// mainnet is down.
+fn config(subgraph_url: &str) -> Config {
+    Config::from_toml(&format!(
+        r#"
+        [[chains]]
+        chain_id = {chain_id}
+
+        [[chains.deployments]]
+        subgraph_url = "{subgraph_url}/{chain_id}"
+        verifying_contract = "0x8E8913197114c911F13cfBfCBBD138C1DC74B964"
+        eip712_name = "boost"
+        eip712_version = "0.1.0"
+
+        [[chains]]
+        chain_id = 1
+
+        [[chains.deployments]]
+        subgraph_url = "{subgraph_url}/down"
+        verifying_contract = "0x8E8913197114c911F13cfBfCBBD138C1DC74B964"
+        eip712_name = "boost"
+        eip712_version = "0.1.0"
+        "#,
+        chain_id = CHAIN_ID,
+        subgraph_url = subgraph_url,
+    ))
+    .unwrap()
+}
+
+// Serves `router` on an ephemeral port and returns its base url.
+async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+fn proposal(id: &str, type_: &str, privacy: &str, scores_by_choice: Vec<u64>) -> ProposalInfo {
+    let scores_by_choice: Vec<VotingPower> = scores_by_choice
+        .into_iter()
+        .map(VotingPower::from)
+        .collect();
+    ProposalInfo {
+        id: id.to_string(),
+        type_: type_.to_string(),
+        score: scores_by_choice.iter().sum(),
+        num_votes: scores_by_choice.len() as u64,
+        scores_by_choice,
+        scores_state: "final".to_string(),
+        end: END,
+        privacy: privacy.to_string(),
+    }
+}
+
+fn vote(voter: &str, voting_power: u64, choice: &str) -> VoteWithChoice {
+    VoteWithChoice {
+        voter: voter.parse().unwrap(),
+        voting_power: VotingPower::from(voting_power),
+        choice: choice.to_string(),
+    }
+}
+
+fn seeded_hub() -> InMemoryHub {
+    let hub = InMemoryHub::default();
+
+    // Basic proposal with a lottery boost
+    let id = "0xc3beb923ad594240e964324c07b6ed0828687d149c3ef30085e8ca844cf11ee1";
+    hub.insert_proposal(proposal(id, "basic", "", vec![60, 25, 15]));
+    hub.insert_vote(
+        id,
+        vote("0x3901D0fDe202aF1427216b79f5243f8A022d68cf", 60, "1"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", 25, "2"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0xc83A9e69012312513328992d454290be85e95101", 15, "3"),
+    );
+
+    // Ranked choice proposal
+    let id = "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb";
+    hub.insert_proposal(proposal(id, "ranked-choice", "", vec![1, 1]));
+    hub.insert_vote(
+        id,
+        vote("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", 1, "[1,2]"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0xc83A9e69012312513328992d454290be85e95101", 1, "[2,1]"),
+    );
+
+    // Shutter proposal
+    let id = "0xdde52de1d892ccc671dcca55504803f87a2297089fd728ef2076af4c1b96ac1c";
+    hub.insert_proposal(proposal(id, "single-choice", "shutter", vec![1, 1]));
+    hub.insert_vote(
+        id,
+        vote("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", 1, "1"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0xc83A9e69012312513328992d454290be85e95101", 1, "2"),
+    );
+
+    // Shutter and ranked choice proposal
+    let id = "0xfcdb01284958142a481fb4d579aa056ed93c29a9f58fbefbfb0504b3c1c06e96";
+    hub.insert_proposal(proposal(id, "ranked-choice", "shutter", vec![1, 1]));
+    hub.insert_vote(
+        id,
+        vote("0xc83A9e69012312513328992d454290be85e95101", 1, "[1,2]"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", 1, "[2,1]"),
+    );
+
+    // Shutter and ranked choice proposal, with a weighted boost
+    let id = "0xe175412d46744bdb68e61c89492a5d3ebb55a487cf8fc4d35a0d671302babed3";
+    hub.insert_proposal(proposal(id, "ranked-choice", "shutter", vec![1, 1]));
+    hub.insert_vote(
+        id,
+        vote("0xc83A9e69012312513328992d454290be85e95101", 1, "[1,2]"),
+    );
+    hub.insert_vote(
+        id,
+        vote("0x5EF29cf961cf3Fc02551B9BdaDAa4418c446c5dd", 1, "[2,1]"),
+    );
+
+    hub
+}
+
+// A boost, as returned by the subgraph
+fn boost(
+    id: &str,
+    proposal: &str,
+    pool_size: &str,
+    end: &str,
+    eligibility: Value,
+    distribution: Value,
+) -> Value {
+    json!({
+        "id": id,
+        "guard": "0x06a85356dcb5b307096726fb86a78c59d38e08ee",
+        "poolSize": pool_size,
+        "currentBalance": pool_size,
+        "start": "0",
+        "end": end,
+        "token": { "id": TOKEN, "decimals": "18" },
+        "strategy": {
+            "name": "proposal",
+            "version": "0.0.1",
+            "proposal": proposal,
+            "eligibility": eligibility,
+            "distribution": distribution,
+        },
+    })
+}
+
+fn boosts() -> HashMap<(String, String), Value> {
+    let incentive = json!({ "type": "incentive", "choice": null });
+    let bribe = json!({ "type": "bribe", "choice": "1" });
+    let even = json!({ "type": "even", "limit": null, "numWinners": null });
+    let weighted = json!({ "type": "weighted", "limit": null, "numWinners": null });
+    let lottery = json!({ "type": "lottery", "limit": null, "numWinners": "1" });
+
+    let pool_size = "30000000000000000000";
+    let ranked = "0x930d5fb011f84d16df26c362d820323f0dab111c3b0b91d75151fe12c5ff07fb";
+    let shutter = "0xdde52de1d892ccc671dcca55504803f87a2297089fd728ef2076af4c1b96ac1c";
+    let shutter_ranked = "0xfcdb01284958142a481fb4d579aa056ed93c29a9f58fbefbfb0504b3c1c06e96";
+    let proportional = "0xe175412d46744bdb68e61c89492a5d3ebb55a487cf8fc4d35a0d671302babed3";
+
+    [
+        boost(
+            "3",
+            "0xc3beb923ad594240e964324c07b6ed0828687d149c3ef30085e8ca844cf11ee1",
+            "10000000000000000",
+            CLAIM_END,
+            incentive.clone(),
+            lottery,
+        ),
+        // Bribes are not allowed on ranked choice or shutter proposals
+        boost(
+            "42",
+            ranked,
+            pool_size,
+            CLAIM_END,
+            bribe.clone(),
+            even.clone(),
+        ),
+        boost(
+            "43",
+            ranked,
+            pool_size,
+            CLAIM_END,
+            incentive.clone(),
+            even.clone(),
+        ),
+        boost(
+            "48",
+            ranked,
+            pool_size,
+            &EXPIRED_CLAIM_END.to_string(),
+            incentive.clone(),
+            even.clone(),
+        ),
+        boost(
+            "44",
+            shutter,
+            pool_size,
+            CLAIM_END,
+            bribe.clone(),
+            even.clone(),
+        ),
+        boost(
+            "45",
+            shutter,
+            pool_size,
+            CLAIM_END,
+            incentive.clone(),
+            even.clone(),
+        ),
+        boost(
+            "46",
+            shutter_ranked,
+            pool_size,
+            CLAIM_END,
+            bribe,
+            even.clone(),
+        ),
+        boost(
+            "47",
+            shutter_ranked,
+            pool_size,
+            CLAIM_END,
+            incentive.clone(),
+            even.clone(),
+        ),
+        boost(
+            "49",
+            proportional,
+            pool_size,
+            CLAIM_END,
+            incentive.clone(),
+            weighted,
+        ),
+        // Boost 50 has been emptied
+        {
+            let mut b = boost("50", ranked, pool_size, CLAIM_END, incentive, even);
+            b["currentBalance"] = json!("0");
+            b
+        },
+    ]
+    .into_iter()
+    .map(|b| {
+        (
+            (b["id"].as_str().unwrap().to_string(), CHAIN_ID.to_string()),
+            b,
+        )
+    })
+    .collect()
+}
+
+pub const CLAIM_TRANSACTION: &str =
+    "0x5f0c5d2cfb5fc2bf4f1a3b7b4a1e9d52d4c8e8a3c4f0b7a1e2d3c4b5a6978801";
+pub const CLAIM_TIMESTAMP: u64 = 1710000000;
+
+// Claims made onchain, as (boost_id, recipient, amount)
+fn claims() -> Vec<(&'static str, &'static str, &'static str)> {
+    vec![(
+        "43",
+        "0xc83a9e69012312513328992d454290be85e95101",
+        "15000000000000000000",
+    )]
+}
+
+// Boosts per page of a `ProposalBoostsQuery`, far fewer than a real subgraph so that the guard
+// has to page through them
+const BOOSTS_PER_PAGE: usize = 2;
+
+// Answers the `BoostQuery`, `ProposalBoostsQuery` and `ClaimsQuery` of the boost subgraph of chain
+// `chain_id`. The subgraph at `/down` fails every query.
+fn fake_subgraph() -> Router {
+    async fn query(
+        AxumState(boosts): AxumState<Arc<HashMap<(String, String), Value>>>,
+        Path(chain_id): Path<String>,
+        Json(body): Json<Value>,
+    ) -> Json<Value> {
+        let query = body["query"].as_str().unwrap_or_default();
+        let variables = &body["variables"];
+
+        if query.contains("claims") {
+            let boost = variables["boost"].as_str().unwrap_or_default();
+            let recipient = variables["recipient"].as_str().unwrap_or_default();
+            let claims: Vec<Value> = claims()
+                .into_iter()
+                .filter(|(b, r, _)| *b == boost && r.eq_ignore_ascii_case(recipient))
+                .map(|(_, r, amount)| {
+                    json!({
+                        "recipient": r,
+                        "amount": amount,
+                        "blockTimestamp": CLAIM_TIMESTAMP.to_string(),
+                        "transactionHash": CLAIM_TRANSACTION,
+                    })
+                })
+                .collect();
+            return Json(json!({ "data": { "claims": claims } }));
+        }
+
+        if let Some(proposals) = variables["proposals"].as_array() {
+            // Ordered by id, as strings
+            let after = variables["after"].as_str().unwrap_or_default();
+            let mut boosts: Vec<(&String, &Value)> = boosts
+                .iter()
+                .filter(|((id, chain), boost)| {
+                    *chain == chain_id
+                        && id.as_str() > after
+                        && proposals.contains(&boost["strategy"]["proposal"])
+                })
+                .map(|((id, _), boost)| (id, boost))
+                .collect();
+            boosts.sort_by_key(|(id, _)| *id);
+            let boosts: Vec<Value> = boosts
+                .into_iter()
+                .take(BOOSTS_PER_PAGE)
+                .map(|(id, boost)| {
+                    json!({
+                        "id": id,
+                        "strategy": { "proposal": boost["strategy"]["proposal"] },
+                    })
+                })
+                .collect();
+            return Json(json!({ "data": { "boosts": boosts } }));
+        }
+
+        let id = variables["id"].as_str().unwrap_or_default();
+        let boost = boosts
+            .get(&(id.to_string(), chain_id))
+            .cloned()
+            .unwrap_or(Value::Null);
+        Json(json!({ "data": { "boost": boost } }))
+    }
+
+    async fn down() -> axum::http::StatusCode {
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    Router::new()
+        .route("/down", post(down))
+        .route("/:chain_id", post(query))
+        .with_state(Arc::new(boosts()))
+}
+
+// Answers the slot and epoch endpoints of a beacon node. Every epoch is finalized.
+fn fake_beacon() -> Router {
+    async fn slot(Path(slot): Path<u64>) -> Json<Value> {
+        Json(json!({ "data": { "epoch": slot / 32, "randaoreveal": RANDAO_REVEAL } }))
+    }
+
+    async fn epoch(Path(_epoch): Path<u64>) -> Json<Value> {
+        Json(json!({ "data": { "finalized": true } }))
+    }
+
+    Router::new()
+        .route("/slot/:slot", get(slot))
+        .route("/epoch/:epoch", get(epoch))
+}
@@ -0,0 +1,233 @@
+//! Fixed-point voting power.
+//!
+//! The hub stores voting power and scores as decimals. They are parsed exactly and carried as
+//! integers scaled by `10^18`, so that rewards are computed without any float rounding and are
+//! reproducible whatever the magnitude of the voting power.
+
+use crate::ServerError;
+use ethers::types::{U256, U512};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+use std::str::FromStr;
+
+/// A non-negative decimal number with 18 decimals, such as a voting power or a score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VotingPower(U256);
+
+impl VotingPower {
+    /// Number of decimals kept. Further decimals are truncated.
+    pub const DECIMALS: usize = 18;
+    pub const ZERO: Self = Self(U256::zero());
+    pub const ONE: Self = Self(U256([1_000_000_000_000_000_000, 0, 0, 0]));
+
+    /// Builds a voting power from its integer representation, scaled by `10^18`.
+    pub fn from_raw(raw: U256) -> Self {
+        Self(raw)
+    }
+
+    /// Integer representation of the voting power, scaled by `10^18`.
+    pub fn raw(self) -> U256 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// Nearest float. It is the float the hub's decimal parses to, as long as that decimal has at
+    /// most 18 decimals.
+    pub fn to_f64(self) -> f64 {
+        // The display is always a valid decimal
+        self.to_string().parse().unwrap()
+    }
+}
+
+/// Returns `amount * numerator / denominator`, rounded down, or 0 if `denominator` is 0.
+/// The product is computed on 512 bits so it can not overflow: the result only saturates if it
+/// does not fit in 256 bits, which requires `numerator > denominator`.
+pub fn mul_div(amount: U256, numerator: U256, denominator: U256) -> U256 {
+    if denominator.is_zero() {
+        return U256::zero();
+    }
+    let result = amount.full_mul(numerator) / U512::from(denominator);
+    U256::try_from(result).unwrap_or(U256::MAX)
+}
+
+impl From<u64> for VotingPower {
+    fn from(value: u64) -> Self {
+        Self(U256::from(value) * Self::ONE.0)
+    }
+}
+
+impl FromStr for VotingPower {
+    type Err = ServerError;
+
+    /// Parses a decimal such as `12`, `0.25` or `1.5e-7`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::InvalidInput(format!("invalid voting power: {:?}", s));
+
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i64>().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+        let mantissa = mantissa.strip_prefix('+').unwrap_or(mantissa);
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = format!("{}{}", int, frac);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let value = U256::from_dec_str(&digits).map_err(|_| invalid())?;
+        // Power of 10 to scale the digits by to get the raw value
+        let shift = Self::DECIMALS as i64 + exponent - frac.len() as i64;
+        let raw = if value.is_zero() {
+            value
+        } else if shift >= 0 {
+            // 10^77 is the largest power of 10 that fits in 256 bits
+            if shift > 77 {
+                return Err(invalid());
+            }
+            value
+                .checked_mul(U256::exp10(shift as usize))
+                .ok_or_else(invalid)?
+        } else if -shift > 77 {
+            U256::zero()
+        } else {
+            value / U256::exp10(-shift as usize)
+        };
+
+        Ok(Self(raw))
+    }
+}
+
+impl fmt::Display for VotingPower {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (int, frac) = self.0.div_mod(Self::ONE.0);
+        let frac = format!("{:0>width$}", frac.to_string(), width = Self::DECIMALS);
+        let frac = frac.trim_end_matches('0');
+        if frac.is_empty() {
+            write!(f, "{}", int)
+        } else {
+            write!(f, "{}.{}", int, frac)
+        }
+    }
+}
+
+// Serialized as a decimal string, so that no precision is lost in JSON
+impl Serialize for VotingPower {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VotingPower {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Add for VotingPower {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl AddAssign for VotingPower {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl Sum for VotingPower {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a VotingPower> for VotingPower {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vp(s: &str) -> VotingPower {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(vp("12"), VotingPower::from(12));
+        assert_eq!(vp("0.25").raw(), U256::exp10(17) * 25 / 10);
+        assert_eq!(vp("1.5e-7").raw(), U256::from(150_000_000_000_u64));
+        assert_eq!(vp("1.2345678901234567e+21"), vp("1234567890123456700000"));
+        assert_eq!(vp("+3.").raw(), U256::exp10(18) * 3);
+        assert_eq!(vp(".5"), vp("0.5"));
+        // Decimals beyond the 18th are truncated
+        assert_eq!(vp("0.0000000000000000019").raw(), U256::from(1));
+        assert_eq!(vp("1e-30"), VotingPower::ZERO);
+
+        for invalid in ["", ".", "-1", "1.2.3", "abc", "1e", "0x10", "1e78"] {
+            assert!(invalid.parse::<VotingPower>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_exact_for_large_voting_power() {
+        // Far beyond the 53 bits of precision of a f64
+        let large = vp("123456789012345678901234567.123456789012345678");
+        assert_eq!(
+            large.to_string(),
+            "123456789012345678901234567.123456789012345678"
+        );
+        assert_eq!(
+            (large + VotingPower::from(1)).to_string(),
+            "123456789012345678901234568.123456789012345678"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(VotingPower::ZERO.to_string(), "0");
+        assert_eq!(vp("60").to_string(), "60");
+        assert_eq!(vp("8.80").to_string(), "8.8");
+        assert_eq!(
+            vp("0.000000000000000001").to_string(),
+            "0.000000000000000001"
+        );
+        assert_eq!(
+            serde_json::to_string(&vp("83.5")).unwrap(),
+            "\"83.5\"".to_string()
+        );
+        assert_eq!(
+            serde_json::from_str::<VotingPower>("\"83.5\"").unwrap(),
+            vp("83.5")
+        );
+    }
+
+    #[test]
+    fn test_mul_div() {
+        let pool = U256::from(100);
+        let share = |voting_power: &str, total: VotingPower| {
+            mul_div(pool, vp(voting_power).raw(), total.raw())
+        };
+        assert_eq!(share("1", vp("3")), U256::from(33));
+        assert_eq!(share("2", vp("3")), U256::from(66));
+        assert_eq!(share("1", VotingPower::ZERO), U256::zero());
+
+        // No overflow, even with the largest pool and voting power
+        assert_eq!(mul_div(U256::MAX, U256::MAX, U256::MAX), U256::MAX);
+        assert_eq!(
+            mul_div(U256::MAX, vp("1").raw(), vp("2").raw()),
+            U256::MAX / 2
+        );
+    }
+}